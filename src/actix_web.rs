@@ -1,16 +1,34 @@
-use crate::with_deadlock_check;
+use crate::{
+    blocking,
+    deadlock::with_task,
+    primitives::{task, Task},
+};
 use actix_web_04::{
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
-    Error,
+    error::BlockingError,
+    http::header::{HeaderName, HeaderValue},
+    web, Error,
 };
 use std::{
     future::{ready, Future, Ready},
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
 };
 
+const LOCK_HOLD_MS: HeaderName = HeaderName::from_static("x-lock-hold-ms");
+const LOCK_WAIT_MS: HeaderName = HeaderName::from_static("x-lock-wait-ms");
+
 pub struct DeadlockDetector;
 
+impl DeadlockDetector {
+    /// Adds the `x-lock-wait-ms` and `x-lock-hold-ms` headers to the responses,
+    /// with the total time the request waited for and held locks.
+    pub fn with_timing_headers(self) -> TimingDeadlockDetector {
+        TimingDeadlockDetector
+    }
+}
+
 impl<S, B> Transform<S, ServiceRequest> for DeadlockDetector
 where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
@@ -24,13 +42,40 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(DeadlockDetectorMiddleware { service }))
+        ready(Ok(DeadlockDetectorMiddleware {
+            service,
+            timing_headers: false,
+        }))
+    }
+}
+
+/// A [DeadlockDetector] reporting the lock timings in the response headers.
+pub struct TimingDeadlockDetector;
+
+impl<S, B> Transform<S, ServiceRequest> for TimingDeadlockDetector
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = DeadlockDetectorMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(DeadlockDetectorMiddleware {
+            service,
+            timing_headers: true,
+        }))
     }
 }
 
 #[doc(hidden)]
 pub struct DeadlockDetectorMiddleware<S> {
     service: S,
+    timing_headers: bool,
 }
 
 impl<S, B> Service<ServiceRequest> for DeadlockDetectorMiddleware<S>
@@ -60,6 +105,7 @@ where
         );
 
         let f = self.service.call(req);
+        let timing_headers = self.timing_headers;
 
         Box::pin(async move {
             #[cfg(feature = "telemetry")]
@@ -79,9 +125,42 @@ where
             #[cfg(feature = "telemetry")]
             let _complete = crate::monitors::CountOnEnd(complete);
 
-            with_deadlock_check(f, task_name).await
+            let task = Arc::new(Task::new(task_name));
+            let mut res = with_task(f, Arc::clone(&task)).await;
+
+            if let (true, Ok(res)) = (timing_headers, &mut res) {
+                let headers = res.headers_mut();
+
+                headers.insert(LOCK_HOLD_MS, millis(task.stats.hold()));
+                headers.insert(LOCK_WAIT_MS, millis(task.stats.wait()));
+            }
+
+            res
         })
     }
 }
 
+/// Runs `f` with [web::block] as the task of the current request, so it may
+/// use the [sync](crate::sync) and [blocking] locks.
+///
+/// The locks held by the request are not seen by the recursive lock detection
+/// inside `f`. Outside of a request, `f` runs without deadlock detection.
+pub async fn block_with_deadlock_check<F, R>(f: F) -> Result<R, BlockingError>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let task = task::current().ok();
+
+    web::block(move || match task {
+        Some(task) => blocking::with_task(f, task),
+        None => f(),
+    })
+    .await
+}
+
+fn millis(d: std::time::Duration) -> HeaderValue {
+    HeaderValue::from(d.as_millis() as u64)
+}
+
 type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T>>>;
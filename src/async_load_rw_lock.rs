@@ -1,59 +1,113 @@
-use std::{fmt, future::Future, mem::replace, ops};
-use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
-
-pub struct AsyncLoadRwLock<T>(RwLock<Option<T>>);
+use crate::{timed_init, Error};
+use parking_lot::Mutex;
+use std::{
+    fmt,
+    future::Future,
+    mem::replace,
+    ops,
+    sync::atomic::{AtomicBool, Ordering::Relaxed},
+};
+use tokio::sync::{watch, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+pub struct AsyncLoadRwLock<T> {
+    closed: AtomicBool,
+    lock: RwLock<Option<T>>,
+    name: &'static str,
+    progress: Mutex<Option<watch::Receiver<f32>>>,
+}
 
 impl<T> AsyncLoadRwLock<T> {
     pub const fn new() -> Self {
         Self::with_opt(None)
     }
 
+    /// Creates an unloaded lock which initialization is reported under `name`
+    /// in the telemetry.
+    pub const fn with_name(name: &'static str) -> Self {
+        Self {
+            closed: AtomicBool::new(false),
+            lock: RwLock::const_new(None),
+            name,
+            progress: Mutex::new(None),
+        }
+    }
+
     pub const fn with_opt(value: Option<T>) -> Self {
-        Self(RwLock::const_new(value))
+        Self {
+            closed: AtomicBool::new(false),
+            lock: RwLock::const_new(value),
+            name: "async-load-rw-lock",
+            progress: Mutex::new(None),
+        }
     }
 
     pub const fn with_val(value: T) -> Self {
         Self::with_opt(Some(value))
     }
 
+    /// Locks with shared read access, returning `None` if the value is not loaded.
+    pub async fn get(&self) -> Option<AsyncLoadRwLockReadGuard<'_, T>> {
+        let guard = self.lock.read().await;
+
+        match guard.is_some() {
+            true => Some(AsyncLoadRwLockReadGuard(guard)),
+            false => None,
+        }
+    }
+
     pub fn get_mut(&mut self) -> &mut Option<T> {
-        self.0.get_mut()
+        self.lock.get_mut()
     }
 
+    /// Returns the value, loading it with `f` if needed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock is [shut down](Self::shutdown).
     pub async fn get_mut_or_init<F>(&mut self, f: F) -> &mut T
     where
         F: Future<Output = T>,
     {
-        let o = self.0.get_mut();
+        self.assert_open();
+        let o = self.lock.get_mut();
 
         if o.is_none() {
-            let v = f.await;
+            let v = timed_init(self.name, f).await;
             *o = Some(v);
         }
 
         o.as_mut().unwrap()
     }
 
+    /// Same as [get_mut_or_init](Self::get_mut_or_init) but fails with
+    /// [Error::Closed] instead of panicking once shut down.
     pub async fn get_mut_or_try_init<F, E>(&mut self, f: F) -> Result<&mut T, E>
     where
         F: Future<Output = Result<T, E>>,
+        E: From<Error>,
     {
-        let o = self.0.get_mut();
+        self.check_open()?;
+        let o = self.lock.get_mut();
 
         if o.is_none() {
-            let v = f.await?;
+            let v = timed_init(self.name, f).await?;
             *o = Some(v);
         }
 
         Ok(o.as_mut().unwrap())
     }
 
+    /// Locks with shared read access, loading the value with `f` if needed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock is [shut down](Self::shutdown) and the value not loaded.
     pub async fn read_or_init<F>(&self, f: F) -> AsyncLoadRwLockReadGuard<'_, T>
     where
         F: Future<Output = T>,
     {
         {
-            let guard = self.0.read().await;
+            let guard = self.lock.read().await;
 
             if guard.is_some() {
                 return AsyncLoadRwLockReadGuard(guard);
@@ -63,12 +117,15 @@ impl<T> AsyncLoadRwLock<T> {
         self.write_or_init(f).await.downgrade()
     }
 
+    /// Same as [read_or_init](Self::read_or_init) but fails with
+    /// [Error::Closed] instead of panicking once shut down.
     pub async fn read_or_try_init<F, E>(&self, f: F) -> Result<AsyncLoadRwLockReadGuard<'_, T>, E>
     where
         F: Future<Output = Result<T, E>>,
+        E: From<Error>,
     {
         {
-            let guard = self.0.read().await;
+            let guard = self.lock.read().await;
 
             if guard.is_some() {
                 return Ok(AsyncLoadRwLockReadGuard(guard));
@@ -78,35 +135,139 @@ impl<T> AsyncLoadRwLock<T> {
         Ok(self.write_or_try_init(f).await?.downgrade())
     }
 
+    /// Same as [read_or_init](Self::read_or_init) but the loader receives a
+    /// [Progress] handle to report its advancement to the
+    /// [subscribers](Self::subscribe_progress).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock is [shut down](Self::shutdown) and the value not loaded.
+    pub async fn read_or_init_with_progress<F, Fut>(&self, f: F) -> AsyncLoadRwLockReadGuard<'_, T>
+    where
+        F: FnOnce(Progress) -> Fut,
+        Fut: Future<Output = T>,
+    {
+        self.read_or_init(self.with_progress(f)).await
+    }
+
+    /// Same as [read_or_try_init](Self::read_or_try_init) but the loader receives a
+    /// [Progress] handle to report its advancement to the
+    /// [subscribers](Self::subscribe_progress).
+    pub async fn read_or_try_init_with_progress<F, Fut, E>(
+        &self,
+        f: F,
+    ) -> Result<AsyncLoadRwLockReadGuard<'_, T>, E>
+    where
+        F: FnOnce(Progress) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: From<Error>,
+    {
+        self.read_or_try_init(self.with_progress(f)).await
+    }
+
+    /// Returns a receiver of the progress reported by the running
+    /// initialization, or `None` if no initialization reports progress.
+    ///
+    /// The receiver is closed once the initialization ends.
+    pub fn subscribe_progress(&self) -> Option<watch::Receiver<f32>> {
+        self.progress.lock().clone()
+    }
+
+    /// Removes the value under the write lock and awaits its teardown by `f`,
+    /// such as flushing it or closing its connections, which `Drop` can't await.
+    ///
+    /// Returns the output of `f`, or `None` if the value was not loaded, and
+    /// [Error::Closed] if the lock is already shut down.
+    ///
+    /// Once shut down, the `try_` initializations fail with [Error::Closed]
+    /// and the others panic.
+    pub async fn shutdown<F, Fut>(&self, f: F) -> Result<Option<Fut::Output>, Error>
+    where
+        F: FnOnce(T) -> Fut,
+        Fut: Future,
+    {
+        let mut guard = self.lock.write().await;
+
+        if self.closed.swap(true, Relaxed) {
+            return Err(Error::Closed);
+        }
+
+        // the guard is held during the teardown so the readers wait for it.
+        Ok(match guard.take() {
+            Some(value) => Some(f(value).await),
+            None => None,
+        })
+    }
+
+    pub fn is_shut_down(&self) -> bool {
+        self.closed.load(Relaxed)
+    }
+
     pub fn swap(&mut self, value: Option<T>) -> Option<T> {
-        replace(self.0.get_mut(), value)
+        replace(self.lock.get_mut(), value)
     }
 
+    /// Locks with exclusive write access, loading the value with `f` if needed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock is [shut down](Self::shutdown).
     pub async fn write_or_init<F>(&self, f: F) -> AsyncLoadRwLockWriteGuard<'_, T>
     where
         F: Future<Output = T>,
     {
-        let mut guard = self.0.write().await;
+        let mut guard = self.lock.write().await;
+        self.assert_open();
 
         if guard.is_none() {
-            *guard = Some(f.await);
+            *guard = Some(timed_init(self.name, f).await);
         }
 
         AsyncLoadRwLockWriteGuard(guard)
     }
 
+    /// Same as [write_or_init](Self::write_or_init) but fails with
+    /// [Error::Closed] instead of panicking once shut down.
     pub async fn write_or_try_init<F, E>(&self, f: F) -> Result<AsyncLoadRwLockWriteGuard<'_, T>, E>
     where
         F: Future<Output = Result<T, E>>,
+        E: From<Error>,
     {
-        let mut guard = self.0.write().await;
+        let mut guard = self.lock.write().await;
+        self.check_open()?;
 
         if guard.is_none() {
-            *guard = Some(f.await?);
+            *guard = Some(timed_init(self.name, f).await?);
         }
 
         Ok(AsyncLoadRwLockWriteGuard(guard))
     }
+
+    fn assert_open(&self) {
+        if self.is_shut_down() {
+            panic!("{} initialized after its shutdown.", self.name);
+        }
+    }
+
+    fn check_open(&self) -> Result<(), Error> {
+        match self.is_shut_down() {
+            true => Err(Error::Closed),
+            false => Ok(()),
+        }
+    }
+
+    /// Wraps the loader so the progress is published only while it runs.
+    async fn with_progress<F, Fut>(&self, f: F) -> Fut::Output
+    where
+        F: FnOnce(Progress) -> Fut,
+        Fut: Future,
+    {
+        let (tx, rx) = watch::channel(0.0);
+        *self.progress.lock() = Some(rx);
+
+        let _clear = ClearProgress(&self.progress);
+        f(Progress(tx)).await
+    }
 }
 
 impl<T> Default for AsyncLoadRwLock<T> {
@@ -115,6 +276,24 @@ impl<T> Default for AsyncLoadRwLock<T> {
     }
 }
 
+/// Reports the advancement of the initialization of an [AsyncLoadRwLock].
+pub struct Progress(watch::Sender<f32>);
+
+impl Progress {
+    /// Publishes the completed fraction of the initialization, between 0 and 1.
+    pub fn report(&self, fraction: f32) {
+        self.0.send_replace(fraction.clamp(0.0, 1.0));
+    }
+}
+
+struct ClearProgress<'a>(&'a Mutex<Option<watch::Receiver<f32>>>);
+
+impl Drop for ClearProgress<'_> {
+    fn drop(&mut self) {
+        *self.0.lock() = None;
+    }
+}
+
 pub struct AsyncLoadRwLockReadGuard<'a, T>(RwLockReadGuard<'a, Option<T>>);
 
 impl<T> fmt::Debug for AsyncLoadRwLockReadGuard<'_, T>
@@ -182,3 +361,61 @@ impl<T> ops::DerefMut for AsyncLoadRwLockWriteGuard<'_, T> {
         self.0.as_mut().unwrap()
     }
 }
+
+#[cfg(test)]
+#[tokio::test]
+async fn subscribe_progress() {
+    let lock = AsyncLoadRwLock::new();
+    assert!(lock.get().await.is_none());
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    let load = lock.read_or_init_with_progress(|progress| async move {
+        progress.report(0.5);
+        rx.await.unwrap();
+        42
+    });
+
+    let watch = async {
+        let mut progress = loop {
+            match lock.subscribe_progress() {
+                Some(progress) => break progress,
+                None => tokio::task::yield_now().await,
+            }
+        };
+
+        progress.wait_for(|v| *v == 0.5).await.unwrap();
+        tx.send(()).unwrap();
+    };
+
+    let (value, _) = tokio::join!(load, watch);
+
+    assert_eq!(*value, 42);
+    assert_eq!(lock.get().await.map(|v| *v), Some(42));
+    assert!(lock.subscribe_progress().is_none());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn shutdown_runs_teardown_once() {
+    let lock = AsyncLoadRwLock::with_name("shutdown_lock");
+    lock.read_or_init(async { vec![1, 2] }).await;
+
+    let flushed = lock.shutdown(|v| async move { v.len() }).await;
+
+    assert_eq!(flushed, Ok(Some(2)));
+    assert!(lock.is_shut_down());
+    assert!(lock.get().await.is_none());
+    assert_eq!(
+        lock.shutdown(|v| async move { v.len() }).await,
+        Err(Error::Closed)
+    );
+
+    let try_init = lock
+        .read_or_try_init(async { Ok::<_, Error>(vec![3]) })
+        .await;
+    assert_eq!(try_init.err(), Some(Error::Closed));
+
+    let init = tokio::spawn(async move { lock.read_or_init(async { vec![3] }).await.len() });
+    assert!(init.await.unwrap_err().is_panic());
+}
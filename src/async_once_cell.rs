@@ -1,4 +1,4 @@
-use crate::sync::async_mutex::Mutex;
+use crate::{sync::async_mutex::Mutex, timed_init};
 use once_cell::sync::OnceCell;
 use std::future::Future;
 
@@ -9,9 +9,15 @@ pub struct AsyncOnceCell<T> {
 
 impl<T> AsyncOnceCell<T> {
     pub const fn new() -> Self {
+        Self::with_name("async-once-cell")
+    }
+
+    /// Creates an empty cell which initialization is reported under `name`
+    /// in the telemetry.
+    pub const fn with_name(name: &'static str) -> Self {
         Self {
             cell: OnceCell::new(),
-            lock: Mutex::new((), "async-once-cell"),
+            lock: Mutex::new((), name),
         }
     }
 
@@ -47,7 +53,7 @@ impl<T> AsyncOnceCell<T> {
             return v;
         }
 
-        let v = f.await;
+        let v = timed_init(self.lock.lock_data.name, f).await;
         self.cell.get_or_init(|| v)
     }
 
@@ -69,7 +75,7 @@ impl<T> AsyncOnceCell<T> {
             return Ok(v);
         }
 
-        let r = f.await;
+        let r = timed_init(self.lock.lock_data.name, f).await;
         self.cell.get_or_try_init(|| r)
     }
 
@@ -98,9 +104,6 @@ impl<T> AsyncOnceCell<T> {
 
 impl<T> Default for AsyncOnceCell<T> {
     fn default() -> Self {
-        Self {
-            cell: OnceCell::new(),
-            lock: Mutex::new((), "async-once-cell"),
-        }
+        Self::new()
     }
 }
@@ -0,0 +1,85 @@
+use crate::primitives::LockData;
+use std::fmt::{self, Debug, Display, Formatter};
+
+/// A description of a change made to the value protected by a lock.
+///
+/// This is produced by the hook registered with
+/// [QueueRwLock::audit_with](crate::QueueRwLock::audit_with) each time a write guard is released.
+#[derive(Clone, Eq, PartialEq)]
+pub struct AuditRecord(String);
+
+impl AuditRecord {
+    pub fn new(summary: impl Into<String>) -> Self {
+        Self(summary.into())
+    }
+
+    pub fn summary(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Debug for AuditRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.0, f)
+    }
+}
+
+impl Display for AuditRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<String> for AuditRecord {
+    fn from(summary: String) -> Self {
+        Self(summary)
+    }
+}
+
+impl From<&str> for AuditRecord {
+    fn from(summary: &str) -> Self {
+        Self(summary.into())
+    }
+}
+
+type DiffFn<T> = dyn Fn(&T, &T) -> AuditRecord + Send + Sync;
+pub(crate) type SinkFn = dyn Fn(AuditRecord) + Send + Sync;
+
+pub(crate) struct Auditor<T> {
+    diff: Box<DiffFn<T>>,
+    sink: Option<Box<SinkFn>>,
+    snapshot: fn(&T) -> T,
+}
+
+impl<T> Auditor<T> {
+    pub fn new<F>(diff: F, sink: Option<Box<SinkFn>>) -> Self
+    where
+        F: Fn(&T, &T) -> AuditRecord + Send + Sync + 'static,
+        T: Clone,
+    {
+        Self {
+            diff: Box::new(diff),
+            sink,
+            snapshot: T::clone,
+        }
+    }
+
+    pub fn snapshot(&self, val: &T) -> T {
+        (self.snapshot)(val)
+    }
+
+    #[cfg_attr(not(feature = "telemetry"), allow(unused_variables))]
+    pub fn record(&self, lock_data: &LockData, old: &T, new: &T) {
+        let record = (self.diff)(old, new);
+
+        #[cfg(feature = "telemetry")]
+        {
+            metrics::counter!("lock_audit_counter", "name" => lock_data.group).increment(1);
+            tracing::info!(name = lock_data.name, record = %record, "lock audit");
+        }
+
+        if let Some(sink) = &self.sink {
+            sink(record);
+        }
+    }
+}
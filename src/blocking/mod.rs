@@ -0,0 +1,50 @@
+//! Locks waiting by blocking the current thread, for code running without
+//! an async runtime.
+//!
+//! The deadlock detection works as for the async locks, inside the thread
+//! context set up by [with_deadlock_check].
+
+#[cfg(not(feature = "unchecked"))]
+use crate::primitives::{locks_held, task, Task};
+#[cfg(not(feature = "unchecked"))]
+use std::sync::Arc;
+
+mod queue_rw_lock;
+
+pub use queue_rw_lock::*;
+
+/// Runs `f` on the current thread with the deadlock detection, as the task
+/// named `task_name`.
+///
+/// With the `unchecked` feature, `f` runs as is.
+pub fn with_deadlock_check<F, R>(f: F, task_name: String) -> R
+where
+    F: FnOnce() -> R,
+{
+    #[cfg(feature = "unchecked")]
+    {
+        let _ = task_name;
+        f()
+    }
+
+    #[cfg(not(feature = "unchecked"))]
+    with_task(f, Arc::new(Task::new(task_name)))
+}
+
+/// Runs `f` as is, no task being tracked with the `unchecked` feature.
+#[cfg(all(feature = "unchecked", feature = "actix_web_04"))]
+pub(crate) fn with_task<F, R>(f: F, _: std::sync::Arc<crate::primitives::Task>) -> R
+where
+    F: FnOnce() -> R,
+{
+    f()
+}
+
+/// Same as [with_deadlock_check] but runs `f` as `task`.
+#[cfg(not(feature = "unchecked"))]
+pub(crate) fn with_task<F, R>(f: F, task: Arc<Task>) -> R
+where
+    F: FnOnce() -> R,
+{
+    locks_held::sync_scope(|| task::sync_scope(f, task))
+}
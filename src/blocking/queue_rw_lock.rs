@@ -0,0 +1,218 @@
+use crate::{
+    primitives::{LockAwaitGuard, LockData, LockHeldGuard},
+    Result, Thresholds,
+};
+use parking_lot::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::ops::{Deref, DerefMut};
+
+/// The blocking flavor of [QueueRwLock](crate::QueueRwLock): readers share
+/// the value with the one task holding the queue, which alone may upgrade
+/// to a write.
+pub struct QueueRwLock<T> {
+    lock_data: LockData,
+    mutex: Mutex<()>,
+    rwlock: RwLock<T>,
+}
+
+impl<T> QueueRwLock<T> {
+    pub const fn new(value: T, name: &'static str) -> Self {
+        Self {
+            lock_data: LockData::new(name),
+            mutex: parking_lot::const_mutex(()),
+            rwlock: parking_lot::const_rwlock(value),
+        }
+    }
+
+    /// Reports the metrics of this lock under `group` instead of its name.
+    pub const fn with_group(mut self, group: &'static str) -> Self {
+        self.lock_data.group = group;
+        self
+    }
+
+    /// Overrides the durations after which waiting for or holding
+    /// this lock is reported in the telemetry.
+    pub const fn with_thresholds(mut self, thresholds: Thresholds) -> Self {
+        self.lock_data.thresholds = thresholds;
+        self
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.rwlock.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.rwlock.into_inner()
+    }
+
+    /// Enqueue to gain access to the write, blocking until the queue is free.
+    pub fn queue(&self) -> Result<QueueRwLockQueueGuard<'_, T>> {
+        if let Some(guard) = self.try_queue() {
+            return Ok(guard);
+        }
+
+        let wait = LockAwaitGuard::new(&self.lock_data, "queue")?;
+        let mutex = self.mutex.lock();
+        let read = self.rwlock.read();
+
+        Ok(QueueRwLockQueueGuard {
+            active: LockHeldGuard::new(wait)?,
+            mutex,
+            queue: self,
+            read,
+        })
+    }
+
+    /// Locks with shared read access, blocking while a write is held.
+    pub fn read(&self) -> Result<QueueRwLockReadGuard<'_, T>> {
+        if let Some(read) = self.rwlock.try_read() {
+            return Ok(QueueRwLockReadGuard {
+                _active: LockHeldGuard::new_no_wait(&self.lock_data, "read")?,
+                read,
+            });
+        }
+
+        let wait = LockAwaitGuard::new(&self.lock_data, "read")?;
+        let read = self.rwlock.read();
+
+        Ok(QueueRwLockReadGuard {
+            _active: LockHeldGuard::new(wait)?,
+            read,
+        })
+    }
+
+    /// Attempts to acquire the queue, and returns `None` if any
+    /// somewhere else is in the queue.
+    pub fn try_queue(&self) -> Option<QueueRwLockQueueGuard<'_, T>> {
+        // mutex must be locked first, before the read.
+        let mutex = self.mutex.try_lock()?;
+        let read = self.rwlock.try_read()?;
+        let active = LockHeldGuard::new_no_wait(&self.lock_data, "queue").ok()?;
+
+        Some(QueueRwLockQueueGuard {
+            active,
+            mutex,
+            queue: self,
+            read,
+        })
+    }
+}
+
+pub struct QueueRwLockReadGuard<'a, T> {
+    _active: LockHeldGuard<'a>,
+    read: RwLockReadGuard<'a, T>,
+}
+
+impl<T> Deref for QueueRwLockReadGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.read
+    }
+}
+
+pub struct QueueRwLockQueueGuard<'a, T> {
+    active: LockHeldGuard<'a>,
+    mutex: MutexGuard<'a, ()>,
+    queue: &'a QueueRwLock<T>,
+    read: RwLockReadGuard<'a, T>,
+}
+
+impl<'a, T> QueueRwLockQueueGuard<'a, T> {
+    /// Locks with exclusive write access, blocking until the readers are gone.
+    ///
+    /// The queue is released once the write is acquired.
+    pub fn write(self) -> Result<QueueRwLockWriteGuard<'a, T>> {
+        let queue = self.queue;
+
+        // the read lock must be dropped before trying to acquire write lock.
+        drop(self.active);
+        drop(self.read);
+
+        if let Some(write) = queue.rwlock.try_write() {
+            // emphasis here that the mutex must be dropped after the write.
+            drop(self.mutex);
+
+            return Ok(QueueRwLockWriteGuard {
+                _active: LockHeldGuard::new_no_wait(&queue.lock_data, "write")?,
+                write,
+            });
+        }
+
+        let wait = LockAwaitGuard::new(&queue.lock_data, "write")?;
+        let write = queue.rwlock.write();
+
+        // emphasis here that the mutex must be dropped after the write.
+        drop(self.mutex);
+
+        Ok(QueueRwLockWriteGuard {
+            _active: LockHeldGuard::new(wait)?,
+            write,
+        })
+    }
+}
+
+impl<T> Deref for QueueRwLockQueueGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.read
+    }
+}
+
+pub struct QueueRwLockWriteGuard<'a, T> {
+    _active: LockHeldGuard<'a>,
+    write: RwLockWriteGuard<'a, T>,
+}
+
+impl<T> Deref for QueueRwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.write
+    }
+}
+
+impl<T> DerefMut for QueueRwLockWriteGuard<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.write
+    }
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[test]
+fn queue_then_write() -> Result<()> {
+    use super::with_deadlock_check;
+    use crate::Error;
+
+    let lock = QueueRwLock::new(0, "main_lock");
+
+    assert_eq!(lock.read().err(), Some(Error::NotDeadlockCheckFuture));
+
+    with_deadlock_check(
+        || {
+            let q = lock.queue()?;
+
+            assert_eq!(
+                lock.queue().err(),
+                Some(Error::RecursiveLock(Default::default()))
+            );
+
+            std::thread::scope(|s| {
+                s.spawn(|| with_deadlock_check(|| lock.read().map(|r| *r), "reader".into()))
+                    .join()
+                    .unwrap()
+            })?;
+
+            *q.write()? = 1;
+
+            assert_eq!(*lock.read()?, 1);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+}
@@ -0,0 +1,88 @@
+use std::{
+    ops::{Deref, DerefMut},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{runtime::Handle, task::JoinHandle, time::sleep};
+
+pub(crate) type BudgetFn = dyn Fn(&BudgetExceeded) + Send + Sync;
+
+/// Describes a guard held past the budget given at its acquisition.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BudgetExceeded {
+    pub budget: Duration,
+    pub name: &'static str,
+    pub op: &'static str,
+}
+
+/// A guard with a hold budget: past the budget, the callback registered on
+/// the lock is invoked while the guard is still held.
+///
+/// Without callback, the overrun is reported in the telemetry.
+pub struct Budgeted<G> {
+    guard: G,
+    _watchdog: Option<Watchdog>,
+}
+
+/// Stops watching the budget when dropped.
+struct Watchdog(JoinHandle<()>);
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+impl<G> Budgeted<G> {
+    pub(crate) fn new(guard: G, exceeded: BudgetExceeded, callback: Option<Arc<BudgetFn>>) -> Self {
+        // without a runtime, there is nothing to run the watchdog.
+        let watchdog = Handle::try_current().ok().map(|handle| {
+            Watchdog(handle.spawn(async move {
+                sleep(exceeded.budget).await;
+
+                #[cfg(feature = "telemetry")]
+                {
+                    metrics::counter!("lock_budget_exceeded_counter", "name" => exceeded.name, "op" => exceeded.op)
+                        .increment(1);
+
+                    tracing::warn!(
+                        budget_ms = exceeded.budget.as_millis(),
+                        name = exceeded.name,
+                        op = exceeded.op,
+                        "Lock budget exceeded",
+                    );
+                }
+
+                if let Some(callback) = callback {
+                    callback(&exceeded);
+                }
+            }))
+        });
+
+        Self {
+            guard,
+            _watchdog: watchdog,
+        }
+    }
+
+    /// Returns the guard, which is no longer watched.
+    pub fn into_inner(self) -> G {
+        self.guard
+    }
+}
+
+impl<G> Deref for Budgeted<G> {
+    type Target = G;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<G> DerefMut for Budgeted<G> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
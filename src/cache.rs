@@ -0,0 +1,205 @@
+use crate::{
+    clock, new_id,
+    primitives::{LockAwaitGuard, LockData, LockHeldGuard},
+    sync::mutex::Mutex,
+    timed_init, Error, Result,
+};
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::OnceCell;
+
+/// A map of values loaded asynchronously, once per key, and evicted after
+/// a time to live or when the capacity is reached.
+///
+/// Concurrent loads of a same key wait for a single initialization. Waiting
+/// for a value being loaded by another task goes through the deadlock
+/// detection, like waiting for a lock.
+pub struct Cache<K, V> {
+    capacity: Option<usize>,
+    entries: Mutex<HashMap<K, Arc<Entry<V>>>>,
+    name: &'static str,
+    ttl: Option<Duration>,
+}
+
+struct Entry<V> {
+    /// Orders the entries by insertion, for the eviction.
+    id: u64,
+    lock_data: LockData,
+    value: OnceCell<(V, Instant)>,
+}
+
+impl<K, V> Cache<K, V>
+where
+    K: Clone + Eq + Hash,
+    V: Clone,
+{
+    /// Creates an unbounded cache which metrics are reported under `name`.
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            capacity: None,
+            entries: Mutex::new(HashMap::new(), name),
+            name,
+            ttl: None,
+        }
+    }
+
+    /// Evicts the oldest entry when inserting past `capacity` entries.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Expires the values `ttl` after they have been loaded.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Removes all the entries.
+    pub fn clear(&self) -> Result<()> {
+        self.entries.lock()?.clear();
+        Ok(())
+    }
+
+    /// Returns a clone of the value of `key` if it is loaded and not expired.
+    pub fn get(&self, key: &K) -> Result<Option<V>> {
+        let entries = self.entries.lock()?;
+
+        Ok(entries
+            .get(key)
+            .filter(|entry| !self.is_expired(entry))
+            .and_then(|entry| entry.value.get())
+            .map(|(value, _)| value.clone()))
+    }
+
+    /// Returns a clone of the value of `key`, loading it with `f` if it is
+    /// absent or expired.
+    pub async fn get_or_init<F>(&self, key: K, f: F) -> Result<V>
+    where
+        F: Future<Output = V>,
+    {
+        self.get_or_try_init(key, async { Ok::<_, Error>(f.await) })
+            .await
+    }
+
+    /// Same as [get_or_init](Self::get_or_init) but the loader may fail, in
+    /// which case the next call loads the value again.
+    pub async fn get_or_try_init<F, E>(&self, key: K, f: F) -> std::result::Result<V, E>
+    where
+        E: From<Error>,
+        F: Future<Output = std::result::Result<V, E>>,
+    {
+        let entry = self.entry(key)?;
+
+        if let Some((value, _)) = entry.value.get() {
+            return Ok(value.clone());
+        }
+
+        let wait = LockAwaitGuard::new(&entry.lock_data, "cache_init")?;
+
+        let (value, _) = entry
+            .value
+            .get_or_try_init(|| async {
+                let _held = LockHeldGuard::new(wait)?;
+                Ok::<_, E>((timed_init(self.name, f).await?, clock::now()))
+            })
+            .await?;
+
+        Ok(value.clone())
+    }
+
+    /// Removes the entry of `key`, returning its value if it was loaded.
+    pub fn invalidate(&self, key: &K) -> Result<Option<V>> {
+        let entry = self.entries.lock()?.remove(key);
+        Ok(entry.and_then(|entry| entry.value.get().map(|(value, _)| value.clone())))
+    }
+
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.entries.lock()?.is_empty())
+    }
+
+    /// The number of entries, including the expired ones not yet evicted.
+    pub fn len(&self) -> Result<usize> {
+        Ok(self.entries.lock()?.len())
+    }
+
+    /// Returns the entry of `key`, replacing it if expired.
+    fn entry(&self, key: K) -> Result<Arc<Entry<V>>> {
+        let mut entries = self.entries.lock()?;
+
+        if let Some(entry) = entries.get(&key) {
+            if !self.is_expired(entry) {
+                #[cfg(feature = "telemetry")]
+                metrics::counter!("cache_hit_counter", "name" => self.name).increment(1);
+
+                return Ok(Arc::clone(entry));
+            }
+
+            entries.remove(&key);
+        }
+
+        #[cfg(feature = "telemetry")]
+        metrics::counter!("cache_miss_counter", "name" => self.name).increment(1);
+
+        if self.capacity.is_some_and(|c| entries.len() >= c) {
+            let oldest = entries.iter().min_by_key(|(_, e)| e.id);
+
+            if let Some(k) = oldest.map(|(k, _)| k.clone()) {
+                entries.remove(&k);
+
+                #[cfg(feature = "telemetry")]
+                metrics::counter!("cache_evict_counter", "name" => self.name).increment(1);
+            }
+        }
+
+        let entry = Arc::new(Entry {
+            id: new_id(),
+            lock_data: LockData::new(self.name),
+            value: OnceCell::new(),
+        });
+
+        entries.insert(key, Arc::clone(&entry));
+        Ok(entry)
+    }
+
+    fn is_expired(&self, entry: &Entry<V>) -> bool {
+        match (self.ttl, entry.value.get()) {
+            (Some(ttl), Some((_, loaded))) => clock::elapsed(*loaded) >= ttl,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn cache_capacity_and_ttl() -> Result<()> {
+    crate::with_deadlock_check(
+        async move {
+            let cache = Cache::new("cache").with_capacity(2);
+
+            assert_eq!(cache.get_or_init(1, async { "a" }).await?, "a");
+            assert_eq!(cache.get_or_init(1, async { "b" }).await?, "a");
+
+            cache.get_or_init(2, async { "b" }).await?;
+            cache.get_or_init(3, async { "c" }).await?;
+
+            assert_eq!(cache.len()?, 2);
+            assert_eq!(cache.get(&1)?, None);
+
+            let cache = Cache::new("cache").with_ttl(Duration::ZERO);
+
+            cache.get_or_init(1, async { "a" }).await?;
+            assert_eq!(cache.get(&1)?, None);
+            assert_eq!(cache.get_or_init(1, async { "b" }).await?, "b");
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
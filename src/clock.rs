@@ -0,0 +1,135 @@
+use once_cell::sync::OnceCell;
+use parking_lot::{const_rwlock, Mutex, RwLock};
+use std::{
+    sync::{
+        atomic::{
+            AtomicBool, AtomicU64,
+            Ordering::{Acquire, Relaxed, Release},
+        },
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+static CLOCK: RwLock<Option<Arc<dyn Clock>>> = const_rwlock(None);
+
+/// Set once a clock is [set](set_clock), so that the default one is read without a lock.
+static CLOCK_SET: AtomicBool = AtomicBool::new(false);
+
+/// A source of time used to measure how long locks are awaited and held.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+}
+
+/// A [Clock] backed by [Instant::now]. This is the default.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A [Clock] backed by [tokio::time::Instant::now], honoring a paused tokio clock.
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
+}
+
+/// A [Clock] that only moves forward when [advance](MockClock::advance) is called.
+pub struct MockClock(Mutex<Instant>);
+
+impl MockClock {
+    pub fn new() -> Self {
+        Self(Mutex::new(Instant::now()))
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.0.lock() += duration;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.0.lock()
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Replaces the clock used by all the locks.
+pub fn set_clock<C: Clock + 'static>(clock: C) {
+    *CLOCK.write() = Some(Arc::new(clock));
+    CLOCK_SET.store(true, Release);
+}
+
+pub(crate) fn now() -> Instant {
+    if !CLOCK_SET.load(Acquire) {
+        return Instant::now();
+    }
+
+    match &*CLOCK.read() {
+        Some(clock) => clock.now(),
+        None => Instant::now(),
+    }
+}
+
+pub(crate) fn elapsed(instant: Instant) -> Duration {
+    now().saturating_duration_since(instant)
+}
+
+/// An optional [Instant] updated without a lock, stored as the nanoseconds
+/// since the first one stored in the process, plus 1 so that 0 means `None`.
+pub(crate) struct AtomicInstant(AtomicU64);
+
+static BASE: OnceCell<Instant> = OnceCell::new();
+
+impl AtomicInstant {
+    pub const fn none() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    pub fn load(&self) -> Option<Instant> {
+        match self.0.load(Relaxed) {
+            0 => None,
+            v => BASE.get().map(|base| *base + Duration::from_nanos(v - 1)),
+        }
+    }
+
+    /// Stores `instant` unless a later one is already stored. The instants
+    /// before the first one stored are rounded up to it.
+    pub fn fetch_max(&self, instant: Instant) {
+        let base = *BASE.get_or_init(|| instant);
+        let nanos = instant.saturating_duration_since(base).as_nanos();
+
+        self.0
+            .fetch_max(u64::try_from(nanos).unwrap_or(u64::MAX - 1) + 1, Relaxed);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn atomic_instant_keeps_the_latest() {
+    let instant = AtomicInstant::none();
+    let now = Instant::now();
+
+    assert_eq!(instant.load(), None);
+
+    instant.fetch_max(now + Duration::from_secs(1));
+    instant.fetch_max(now);
+
+    assert_eq!(instant.load(), Some(now + Duration::from_secs(1)));
+}
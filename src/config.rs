@@ -0,0 +1,99 @@
+use crate::Thresholds;
+use once_cell::sync::OnceCell;
+use std::time::Duration;
+
+static CONFIG: OnceCell<Config> = OnceCell::new();
+
+#[cfg(all(test, not(feature = "unchecked")))]
+thread_local! {
+    /// The configuration of the current test, set by [Config::scoped].
+    static SCOPED: std::cell::Cell<Option<&'static Config>> = const { std::cell::Cell::new(None) };
+}
+
+const DEFAULT: Config = Config::new();
+
+/// The settings of the process, [installed](Config::install) once at startup.
+///
+/// The settings of a lock, such as its [Thresholds], take precedence.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Config {
+    /// Reports the guards of the [sync](crate::sync) locks held while their
+    /// task is suspended, once dropped, in the
+    /// [LockStats](crate::LockStats) and the telemetry. Off by default.
+    pub detect_sync_guard_across_await: bool,
+
+    /// The interval of the rate limited error logs of the `telemetry`
+    /// feature: the errors of a same kind on a same lock are logged once per
+    /// interval, the next log reporting the number suppressed in between.
+    /// Zero logs every error. Defaults to 10 seconds.
+    pub error_log_interval: Duration,
+
+    /// The number of locks a task may hold at once, acquiring one more
+    /// returning [Error::TooManyLocksHeld](crate::Error::TooManyLocksHeld). Unlimited by default.
+    pub max_locks_held: Option<usize>,
+
+    /// How long the synchronous locks wait before returning
+    /// [Error::SyncLockForTooLong](crate::Error::SyncLockForTooLong). Defaults to 250 ms.
+    pub sync_lock_timeout: Duration,
+
+    /// The thresholds of the locks leaving them unset.
+    pub thresholds: Thresholds,
+}
+
+impl Config {
+    pub const fn new() -> Self {
+        Self {
+            detect_sync_guard_across_await: false,
+            error_log_interval: Duration::from_secs(10),
+            max_locks_held: None,
+            sync_lock_timeout: Duration::from_millis(250),
+            thresholds: Thresholds::new(),
+        }
+    }
+
+    /// Installs this configuration for the rest of the process, returning it
+    /// back if one is already installed.
+    ///
+    /// Install it before creating the locks: the locks used beforehand may
+    /// have run with the defaults.
+    #[allow(clippy::result_large_err)]
+    pub fn install(self) -> Result<(), Self> {
+        CONFIG.set(self)
+    }
+
+    /// The installed configuration, or the defaults.
+    pub fn get() -> &'static Self {
+        #[cfg(all(test, not(feature = "unchecked")))]
+        if let Some(config) = SCOPED.get() {
+            return config;
+        }
+
+        CONFIG.get().unwrap_or(&DEFAULT)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Restores the configuration replaced by [Config::scoped] once dropped.
+#[cfg(all(test, not(feature = "unchecked")))]
+pub(crate) struct ScopedConfig(Option<&'static Config>);
+
+#[cfg(all(test, not(feature = "unchecked")))]
+impl Config {
+    /// Uses this configuration on the current thread until the returned
+    /// guard is dropped, leaving the installed one to the other tests.
+    pub(crate) fn scoped(self) -> ScopedConfig {
+        ScopedConfig(SCOPED.replace(Some(Box::leak(Box::new(self)))))
+    }
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+impl Drop for ScopedConfig {
+    fn drop(&mut self) {
+        SCOPED.set(self.0);
+    }
+}
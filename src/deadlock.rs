@@ -1,23 +1,172 @@
-use crate::primitives::{locks_held, task};
+#[cfg(not(feature = "unchecked"))]
+use crate::primitives::locks_held;
+use crate::{
+    primitives::{task, Task},
+    Error, Result,
+};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{atomic::Ordering::Relaxed, Arc},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::task::{JoinError, JoinHandle};
 
+/// Runs `f` as the task named `task_name`, tracking the locks it holds and
+/// awaits for the deadlock detection.
+///
+/// With the `unchecked` feature, `f` runs as is.
 pub async fn with_deadlock_check<F, R>(f: F, task_name: String) -> R
 where
-    F: std::future::Future<Output = R>,
+    F: Future<Output = R>,
+{
+    #[cfg(feature = "unchecked")]
+    {
+        let _ = task_name;
+        f.await
+    }
+
+    #[cfg(not(feature = "unchecked"))]
+    with_task(f, Arc::new(Task::new(task_name))).await
+}
+
+/// Runs `f` as is, no task being tracked with the `unchecked` feature.
+#[cfg(feature = "unchecked")]
+pub(crate) async fn with_task<F, R>(f: F, _: Arc<Task>) -> R
+where
+    F: Future<Output = R>,
+{
+    f.await
+}
+
+/// Same as [with_deadlock_check] but runs `f` as `task`, so its statistics
+/// can be read afterward.
+#[cfg(not(feature = "unchecked"))]
+pub(crate) async fn with_task<F, R>(f: F, task: Arc<Task>) -> R
+where
+    F: Future<Output = R>,
 {
     #[cfg(feature = "telemetry")]
     let _active = crate::monitors::ActiveGauge::new(
-        metrics::gauge!("active_dl_chk_gauge", "task" => task_name.clone()),
+        metrics::gauge!("active_dl_chk_gauge", "task" => task.name().to_string()),
     );
 
     #[cfg(feature = "telemetry")]
-    metrics::counter!("started_dl_chk_counter", "task" => task_name.clone()).increment(1);
+    metrics::counter!("started_dl_chk_counter", "task" => task.name().to_string()).increment(1);
 
     #[cfg(feature = "telemetry")]
     let _on_complete = crate::monitors::CountOnEnd(
-        metrics::counter!("completed_dl_chk_counter", "task" => task_name.clone()),
+        metrics::counter!("completed_dl_chk_counter", "task" => task.name().to_string()),
     );
 
-    locks_held::scope(task::scope(f, task_name)).await
+    let polls = Arc::clone(&task);
+    let mut f = std::pin::pin!(f);
+
+    // counts the polls to tell the sync guards held across an await.
+    let f = std::future::poll_fn(move |cx| {
+        polls.polls.fetch_add(1, Relaxed);
+        f.as_mut().poll(cx)
+    });
+
+    locks_held::scope(task::scope(f, task)).await
+}
+
+/// Runs `f` under `task_name` inside the current [with_deadlock_check],
+/// keeping the locks held by the task.
+///
+/// The locks awaited and the errors raised while polling `f` are attributed to
+/// `task_name` in the telemetry. Outside of a `with_deadlock_check`, `f` runs as is.
+pub async fn rename_task<F, R>(task_name: String, f: F) -> R
+where
+    F: Future<Output = R>,
+{
+    let Ok(task) = task::current() else {
+        return f.await;
+    };
+
+    let name: Arc<str> = task_name.into();
+    let mut f = std::pin::pin!(f);
+
+    std::future::poll_fn(|cx| {
+        let _restore = RestoreName(&task, task.replace_name(Arc::clone(&name)));
+        f.as_mut().poll(cx)
+    })
+    .await
+}
+
+/// Restores the name of the task on drop, even if the poll panics.
+struct RestoreName<'a>(&'a Task, Arc<str>);
+
+impl Drop for RestoreName<'_> {
+    fn drop(&mut self) {
+        self.0.replace_name(Arc::clone(&self.1));
+    }
+}
+
+/// Spawns `f` on the tokio runtime inside a [with_deadlock_check] named
+/// `task_name`, returning a handle exposing the lock statistics of the task.
+pub fn spawn_with_deadlock_check<F>(f: F, task_name: String) -> DeadlockCheckHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let task = Arc::new(Task::new(task_name));
+    let handle = tokio::spawn(with_task(f, Arc::clone(&task)));
+
+    DeadlockCheckHandle { handle, task }
+}
+
+/// A [JoinHandle] of a task spawned with [spawn_with_deadlock_check].
+pub struct DeadlockCheckHandle<T> {
+    handle: JoinHandle<T>,
+    task: Arc<Task>,
+}
+
+impl<T> DeadlockCheckHandle<T> {
+    pub fn abort(&self) {
+        self.handle.abort();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// The lock statistics of the task so far, final once it has completed.
+    pub fn stats(&self) -> LockStats {
+        self.task.stats.lock_stats()
+    }
+}
+
+impl<T> Future for DeadlockCheckHandle<T> {
+    type Output = std::result::Result<T, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.handle).poll(cx)
+    }
+}
+
+/// Totals of the locks of a task spawned with [spawn_with_deadlock_check].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LockStats {
+    /// The number of locks acquired.
+    pub acquired: u64,
+
+    /// The number of lock errors raised in the task.
+    pub errors: u64,
+
+    /// The last lock errors raised in the task, the oldest first, up to 16.
+    pub last_errors: Vec<Error>,
+
+    /// The longest time a lock has been held.
+    pub max_hold: Duration,
+
+    /// The number of sync guards held across an await, when
+    /// [detected](crate::Config::detect_sync_guard_across_await).
+    pub sync_guards_across_await: u64,
+
+    /// The total time spent waiting for locks.
+    pub wait: Duration,
 }
 
 /// Log a "Lock held" warn in the trace if a lock is currently active.
@@ -28,3 +177,173 @@ pub fn warn_lock_held() {
         let _ = tracing::warn_span!("Lock held").entered();
     }
 }
+
+/// Enters a section in which acquiring any lock of this crate returns
+/// [Error::LockFreeSection](crate::Error::LockFreeSection), until the
+/// returned guard is dropped.
+///
+/// This is useful to make sure no lock is held while calling an external api.
+pub fn lock_free_section() -> Result<LockFreeSection> {
+    LockFreeSection::enter(false)
+}
+
+/// Same as [lock_free_section] but acquiring a lock panics.
+pub fn strict_lock_free_section() -> Result<LockFreeSection> {
+    LockFreeSection::enter(true)
+}
+
+pub struct LockFreeSection {
+    strict: bool,
+    task: Arc<Task>,
+}
+
+impl LockFreeSection {
+    fn enter(strict: bool) -> Result<Self> {
+        let task = task::current()?;
+
+        #[cfg(feature = "telemetry")]
+        warn_lock_held();
+
+        match strict {
+            true => task.lock_free_strict.fetch_add(1, Relaxed),
+            false => task.lock_free.fetch_add(1, Relaxed),
+        };
+
+        Ok(Self { strict, task })
+    }
+}
+
+impl Drop for LockFreeSection {
+    fn drop(&mut self) {
+        match self.strict {
+            true => self.task.lock_free_strict.fetch_sub(1, Relaxed),
+            false => self.task.lock_free.fetch_sub(1, Relaxed),
+        };
+    }
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn lock_free_section_forbids_locks() -> Result<()> {
+    use crate::{Error, QueueRwLock};
+
+    with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new((), "main_lock");
+            let section = lock_free_section()?;
+
+            assert_eq!(lock.read().await.unwrap_err(), Error::LockFreeSection);
+            assert!(lock.try_queue().is_none());
+
+            drop(section);
+
+            assert!(lock.read().await.is_ok());
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn spawned_task_stats() {
+    use crate::QueueRwLock;
+
+    let lock = Arc::new(QueueRwLock::new((), "main_lock"));
+    let l = Arc::clone(&lock);
+
+    let mut handle = spawn_with_deadlock_check(
+        async move {
+            drop(l.read().await.unwrap());
+
+            let _q = l.queue().await.unwrap();
+            assert!(l.queue().await.is_err());
+        },
+        "lock_test".into(),
+    );
+
+    (&mut handle).await.unwrap();
+
+    let stats = handle.stats();
+
+    assert!(handle.is_finished());
+    assert_eq!(stats.acquired, 2);
+    assert_eq!(stats.errors, 1);
+    assert_eq!(
+        stats.last_errors,
+        [Error::RecursiveLock(Default::default())]
+    );
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn last_errors_are_bounded() {
+    use crate::{primitives::task::ERRORS_KEPT, QueueRwLock};
+
+    let l = QueueRwLock::new((), "main_lock");
+
+    let mut handle = spawn_with_deadlock_check(
+        async move {
+            let _q = l.queue().await.unwrap();
+
+            for _ in 0..ERRORS_KEPT + 4 {
+                assert!(l.queue().await.is_err());
+            }
+        },
+        "lock_test".into(),
+    );
+
+    (&mut handle).await.unwrap();
+
+    let stats = handle.stats();
+
+    assert_eq!(stats.errors, ERRORS_KEPT as u64 + 4);
+    assert_eq!(
+        stats.last_errors,
+        vec![Error::RecursiveLock(Default::default()); ERRORS_KEPT]
+    );
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn rename_task_keeps_locks_held() -> Result<()> {
+    use crate::QueueRwLock;
+
+    with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new((), "main_lock");
+            let _q = lock.queue().await?;
+
+            rename_task("sub_task".into(), async {
+                assert_eq!(&*task::current()?.name(), "sub_task");
+                assert_eq!(
+                    lock.queue().await.unwrap_err(),
+                    Error::RecursiveLock(Default::default())
+                );
+                Ok::<_, Error>(())
+            })
+            .await?;
+
+            assert_eq!(&*task::current()?.name(), "lock_test");
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(all(test, feature = "unchecked"))]
+#[tokio::test]
+async fn unchecked_runs_as_is() {
+    with_deadlock_check(
+        async {
+            assert!(task::current().is_err());
+            assert!(task::live().is_empty());
+        },
+        "lock_test".into(),
+    )
+    .await
+}
@@ -0,0 +1,174 @@
+#[cfg(not(feature = "unchecked"))]
+use crate::primitives::locks_held::LockHeld;
+use crate::primitives::task;
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+
+type Edges = BTreeMap<(&'static str, &'static str), u64>;
+
+/// The edges of the tasks dropped so far.
+static EDGES: Mutex<Edges> = Mutex::new(BTreeMap::new());
+
+/// A lock acquired while another lock was held, by lock names.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LockDependency {
+    pub held: &'static str,
+    pub acquired: &'static str,
+
+    /// The number of times `acquired` has been taken while holding `held`.
+    pub count: u64,
+}
+
+/// Returns the edges accumulated since the start of the process, sorted by
+/// the held lock then the acquired lock.
+///
+/// Two locks appearing in both directions are acquired in inconsistent orders.
+pub fn lock_dependency_graph() -> Vec<LockDependency> {
+    graph()
+        .iter()
+        .map(|(&(held, acquired), &count)| LockDependency {
+            held,
+            acquired,
+            count,
+        })
+        .collect()
+}
+
+/// Returns a cycle of the observed graph, starting and ending with the
+/// same lock name, or `None` if the locks are always taken in a consistent order.
+///
+/// Locks of a same name nested in each other are not considered a cycle.
+pub(crate) fn find_cycle() -> Option<Vec<&'static str>> {
+    cycle(&graph())
+}
+
+/// The edges of the tasks dropped so far and of the live ones.
+fn graph() -> Edges {
+    let mut edges = EDGES.lock().clone();
+
+    for task in task::live() {
+        merge(&mut edges, &task.edges.0.lock());
+    }
+
+    edges
+}
+
+fn merge(edges: &mut Edges, other: &Edges) {
+    for (&edge, &count) in other {
+        *edges.entry(edge).or_default() += count;
+    }
+}
+
+fn cycle(edges: &Edges) -> Option<Vec<&'static str>> {
+    fn visit(
+        edges: &Edges,
+        node: &'static str,
+        path: &mut Vec<&'static str>,
+        done: &mut Vec<&'static str>,
+    ) -> Option<Vec<&'static str>> {
+        if let Some(start) = path.iter().position(|n| *n == node) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(node);
+            return Some(cycle);
+        }
+
+        if done.contains(&node) {
+            return None;
+        }
+
+        path.push(node);
+
+        for &(_, next) in edges
+            .keys()
+            .filter(|(held, acquired)| *held == node && acquired != held)
+        {
+            if let Some(cycle) = visit(edges, next, path, done) {
+                return Some(cycle);
+            }
+        }
+
+        path.pop();
+        done.push(node);
+
+        None
+    }
+
+    let mut done = Vec::new();
+
+    edges
+        .keys()
+        .find_map(|&(held, _)| visit(edges, held, &mut Vec::new(), &mut done))
+}
+
+/// The edges recorded by a task, merged in the graph of the process once
+/// the task is dropped so that the tasks do not contend while recording.
+#[derive(Default)]
+pub(crate) struct TaskEdges(Mutex<Edges>);
+
+impl TaskEdges {
+    #[cfg(not(feature = "unchecked"))]
+    pub fn record(&self, locks_held: &[LockHeld], acquired: &'static str) {
+        let mut edges = self.0.lock();
+
+        for held in locks_held {
+            *edges.entry((held.name, acquired)).or_default() += 1;
+        }
+    }
+}
+
+impl Drop for TaskEdges {
+    fn drop(&mut self) {
+        let edges = self.0.get_mut();
+
+        if !edges.is_empty() {
+            merge(&mut EDGES.lock(), edges);
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn nested_locks_are_recorded() -> crate::Result<()> {
+    crate::with_deadlock_check(
+        async move {
+            let a = crate::QueueRwLock::new((), "graph_a");
+            let b = crate::QueueRwLock::new((), "graph_b");
+
+            let _a = a.read().await?;
+            let _b = b.read().await?;
+
+            assert!(lock_dependency_graph()
+                .iter()
+                .any(|d| (d.held, d.acquired) == ("graph_a", "graph_b")));
+
+            assert!(!lock_dependency_graph()
+                .iter()
+                .any(|d| (d.held, d.acquired) == ("graph_b", "graph_a")));
+
+            Ok::<_, crate::Error>(())
+        },
+        "lock_test".into(),
+    )
+    .await?;
+
+    // kept once the task has ended.
+    assert!(lock_dependency_graph()
+        .iter()
+        .any(|d| (d.held, d.acquired) == ("graph_a", "graph_b")));
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn cycle_is_found() {
+    let mut edges = Edges::new();
+
+    edges.insert(("a", "a"), 1);
+    edges.insert(("a", "b"), 1);
+    edges.insert(("b", "c"), 1);
+    assert_eq!(cycle(&edges), None);
+
+    edges.insert(("c", "a"), 1);
+    assert_eq!(cycle(&edges), Some(vec!["a", "b", "c", "a"]));
+}
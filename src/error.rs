@@ -1,18 +1,103 @@
 use crate::primitives::LockData;
+#[cfg(not(feature = "unchecked"))]
+use crate::primitives::{locks_held::LockHeld, LockedTask};
 use std::{
     error,
     fmt::{self, Formatter},
+    panic::Location,
+    time::Duration,
 };
 
-#[derive(Clone, Copy, Eq, PartialEq)]
+#[cfg(feature = "telemetry")]
+use crate::error_log::should_log;
+
+#[cfg(feature = "backtrace")]
+use std::{backtrace::Backtrace, sync::Arc};
+
+#[derive(Clone, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum Error {
-    DeadlockDetected,
-    RecursiveLock,
+    /// The lock could not be acquired within the given timeout.
+    AcquireTimeout,
+
+    /// The lock has been closed to the writes.
+    Closed,
+    DeadlockDetected(Trace),
+
+    /// A lock marked as forbidden in async, or a blocking accessor such as
+    /// [blocking_read](crate::QueueRwLock::blocking_read), has been used from
+    /// async code, at the given location.
+    ForbiddenInAsync(&'static Location<'static>),
+
+    LeaseExpired,
+    LockFreeSection,
+    LockOrderConflict,
+    RecursiveLock(Trace),
     NotDeadlockCheckFuture,
-    SyncLockForTooLong,
+
+    /// A writer panicked, leaving the value of the lock possibly half changed.
+    Poisoned,
+
+    SyncLockForTooLong(Trace),
+
+    /// The task already holds [Config::max_locks_held](crate::Config::max_locks_held) locks.
+    TooManyLocksHeld,
+}
+
+/// Where a [DeadlockDetected](Error::DeadlockDetected),
+/// [RecursiveLock](Error::RecursiveLock) or
+/// [SyncLockForTooLong](Error::SyncLockForTooLong) error has been raised.
+///
+/// With the `backtrace` feature, it holds a backtrace captured with
+/// [Backtrace::capture](std::backtrace::Backtrace::capture), thus only resolved when `RUST_BACKTRACE` or
+/// `RUST_LIB_BACKTRACE` enable it. It is ignored by the comparisons.
+#[derive(Clone, Default)]
+pub struct Trace {
+    #[cfg(feature = "backtrace")]
+    backtrace: Option<Arc<Backtrace>>,
+}
+
+impl Trace {
+    fn capture() -> Self {
+        Self {
+            #[cfg(feature = "backtrace")]
+            backtrace: Some(Arc::new(Backtrace::capture())),
+        }
+    }
+}
+
+impl fmt::Debug for Trace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("Trace")
+    }
+}
+
+impl Eq for Trace {}
+
+impl PartialEq for Trace {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
 }
 
 impl Error {
+    /// Returns the backtrace captured when this error has been raised.
+    #[cfg(feature = "backtrace")]
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        match self {
+            Self::DeadlockDetected(trace)
+            | Self::RecursiveLock(trace)
+            | Self::SyncLockForTooLong(trace) => trace.backtrace.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Records the error in the statistics of the current task.
+    fn raised(self) -> Self {
+        let _ = crate::primitives::task::try_with(|task| task.stats.record_error(self.clone()));
+        self
+    }
+
     pub(crate) fn not_deadlock_check_future<E>(_: E) -> Self {
         Self::NotDeadlockCheckFuture
     }
@@ -20,60 +105,246 @@ impl Error {
     #[allow(unused_variables)]
     pub(crate) fn deadlock_detected(lock_data: &LockData, op: &str, locked_task: &str) -> Self {
         #[cfg(feature = "telemetry")]
-        {
+        log_deadlock(lock_data, op, locked_task, None);
+
+        Self::DeadlockDetected(Trace::capture()).raised()
+    }
+
+    /// Same as [deadlock_detected](Self::deadlock_detected), linking the
+    /// error span to the span in which `holder` has acquired the lock.
+    #[allow(unused_variables)]
+    #[cfg(not(feature = "unchecked"))]
+    pub(crate) fn deadlock_detected_by(
+        lock_data: &LockData,
+        op: &str,
+        holder: &LockedTask,
+    ) -> Self {
+        #[cfg(feature = "telemetry")]
+        log_deadlock(lock_data, op, &holder.task.name(), Some(&holder.span));
+
+        Self::DeadlockDetected(Trace::capture()).raised()
+    }
+
+    #[allow(unused_variables)]
+    pub(crate) fn forbidden_in_async(
+        lock_data: &LockData,
+        op: &str,
+        location: &'static Location<'static>,
+    ) -> Self {
+        #[cfg(feature = "telemetry")]
+        if let Some(suppressed) = should_log(lock_data.name, "forbidden in async") {
+            tracing::error!(
+                lock = lock_data.name,
+                op = op,
+                location = %location,
+                suppressed,
+                "lock forbidden in async acquired from async",
+            );
+        }
+
+        Self::ForbiddenInAsync(location).raised()
+    }
+
+    #[allow(unused_variables)]
+    #[cfg(not(feature = "unchecked"))]
+    pub(crate) fn lock_free_section(lock_data: &LockData, op: &str, task: &str) -> Self {
+        #[cfg(feature = "telemetry")]
+        if let Some(suppressed) = should_log(lock_data.name, "lock free section") {
+            tracing::error!(
+                lock = lock_data.name,
+                op = op,
+                task = task,
+                suppressed,
+                "lock acquired inside a lock free section",
+            );
+        }
+
+        Self::LockFreeSection.raised()
+    }
+
+    #[allow(unused_variables)]
+    pub(crate) fn lock_order_conflict(first: &'static str, second: &str, other_task: &str) -> Self {
+        #[cfg(feature = "telemetry")]
+        if let Some(suppressed) = should_log(first, "lock order conflict") {
             let _ = crate::primitives::task::try_with(|task| {
                 tracing::error!(
-                    lock = lock_data.name,
-                    op = op,
-                    await_task = task.name,
-                    locked_task = locked_task,
-                    "deadlock detected"
+                    first = first,
+                    second = second,
+                    task = %task.name(),
+                    other_task = other_task,
+                    suppressed,
+                    "lock order conflict",
                 );
-
-                let _ = tracing::error_span!(parent: None, "deadlock detected", lock = lock_data.name, op = op, await_task = task.name, locked_task = locked_task)
-                    .entered();
             });
         }
 
-        Self::DeadlockDetected
+        Self::LockOrderConflict.raised()
     }
 
     #[allow(unused_variables)]
+    #[cfg(not(feature = "unchecked"))]
     pub(crate) fn recursive_lock(lock_data: &LockData, op: &str) -> Self {
         #[cfg(feature = "telemetry")]
-        {
+        if let Some(suppressed) = should_log(lock_data.name, "recursive lock") {
             let _ = crate::primitives::task::try_with(|task| {
+                let holder_span = lock_data.holder_span(task).and_then(|s| s.id());
+
                 tracing::error!(
                     lock = lock_data.name,
                     op = op,
-                    task = task.name,
+                    task = %task.name(),
+                    holder_span = holder_span.as_ref().map(tracing::Id::into_u64),
+                    suppressed,
                     "recursive lock",
                 );
 
-                let _ = tracing::error_span!(
+                let span = tracing::error_span!(
                     parent: None,
                     "recursive lock",
                     lock = lock_data.name,
                     op = op,
-                    task = task.name
-                )
-                .entered();
+                    task = %task.name(),
+                    holder_span = holder_span.as_ref().map(tracing::Id::into_u64),
+                );
+
+                span.follows_from(holder_span);
+                let _ = span.entered();
             });
         }
 
-        Self::RecursiveLock
+        Self::RecursiveLock(Trace::capture()).raised()
     }
+
+    #[allow(unused_variables)]
+    pub(crate) fn acquire_timeout(lock_data: &LockData, op: &str, timeout: Duration) -> Self {
+        #[cfg(feature = "telemetry")]
+        if let Some(suppressed) = should_log(lock_data.name, "lock acquire timeout") {
+            let _ = crate::primitives::task::try_with(|task| {
+                tracing::error!(
+                    lock = lock_data.name,
+                    op = op,
+                    task = %task.name(),
+                    timeout_ms = timeout.as_millis(),
+                    suppressed,
+                    "lock acquire timeout",
+                );
+            });
+        }
+
+        Self::AcquireTimeout.raised()
+    }
+
+    #[allow(unused_variables)]
+    pub(crate) fn sync_lock_for_too_long(lock_data: &LockData, op: &str) -> Self {
+        #[cfg(feature = "telemetry")]
+        if let Some(suppressed) = should_log(lock_data.name, "synchronous lock for too long") {
+            // the longest holder, unknown when held outside of a deadlock check future.
+            let mut holder: Option<(String, std::time::Instant)> = None;
+
+            lock_data.for_each_holder(|name, since| {
+                if holder.as_ref().is_none_or(|(_, s)| since < *s) {
+                    holder = Some((name.to_owned(), since));
+                }
+            });
+
+            let task = crate::primitives::task::try_with(|task| task.name()).ok();
+
+            tracing::error!(
+                lock = lock_data.name,
+                op = op,
+                task = task.as_deref(),
+                holder_task = holder.as_ref().map(|(name, _)| name.as_str()),
+                held_ms = holder
+                    .as_ref()
+                    .map(|(_, since)| crate::clock::elapsed(*since).as_millis() as u64),
+                suppressed,
+                "synchronous lock for too long",
+            );
+        }
+
+        Self::SyncLockForTooLong(Trace::capture()).raised()
+    }
+
+    #[allow(unused_variables)]
+    #[cfg(not(feature = "unchecked"))]
+    pub(crate) fn too_many_locks_held(lock_data: &LockData, op: &str, held: &[LockHeld]) -> Self {
+        #[cfg(feature = "telemetry")]
+        if let Some(suppressed) = should_log(lock_data.name, "too many locks held") {
+            let held: Vec<_> = held.iter().map(|l| l.name).collect();
+
+            tracing::error!(
+                lock = lock_data.name,
+                op = op,
+                held = ?held,
+                suppressed,
+                "too many locks held",
+            );
+        }
+
+        Self::TooManyLocksHeld.raised()
+    }
+}
+
+#[cfg(feature = "telemetry")]
+fn log_deadlock(
+    lock_data: &LockData,
+    op: &str,
+    locked_task: &str,
+    holder_span: Option<&tracing::Span>,
+) {
+    let holder_span = holder_span.and_then(tracing::Span::id);
+
+    let Some(suppressed) = should_log(lock_data.name, "deadlock detected") else {
+        return;
+    };
+
+    let _ = crate::primitives::task::try_with(|task| {
+        tracing::error!(
+            lock = lock_data.name,
+            op = op,
+            await_task = %task.name(),
+            locked_task = locked_task,
+            holder_span = holder_span.as_ref().map(tracing::Id::into_u64),
+            suppressed,
+            "deadlock detected"
+        );
+
+        let span = tracing::error_span!(
+            parent: None,
+            "deadlock detected",
+            lock = lock_data.name,
+            op = op,
+            await_task = %task.name(),
+            locked_task = locked_task,
+            holder_span = holder_span.as_ref().map(tracing::Id::into_u64),
+        );
+
+        span.follows_from(holder_span);
+        let _ = span.entered();
+    });
 }
 
 impl fmt::Debug for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
-            Self::DeadlockDetected => f.write_str("Deadlock detected."),
+            Self::AcquireTimeout => f.write_str("Lock acquire timed out."),
+            Self::Closed => f.write_str("Lock closed."),
+            Self::DeadlockDetected(_) => f.write_str("Deadlock detected."),
+            Self::ForbiddenInAsync(location) => {
+                write!(f, "Lock forbidden in async acquired at {location}.")
+            }
+            Self::LeaseExpired => f.write_str("Lease expired."),
+            Self::LockFreeSection => f.write_str("Lock acquired inside a lock free section."),
+            Self::LockOrderConflict => {
+                f.write_str("Locks declared in the opposite order by another task.")
+            }
             Self::NotDeadlockCheckFuture => {
                 f.write_str("Must run inside a with_deadlock_check future.")
             }
-            Self::RecursiveLock => f.write_str("Recursive lock."),
-            Self::SyncLockForTooLong => f.write_str("Synchronous lock for too long"),
+            Self::Poisoned => f.write_str("Lock poisoned by a panicking writer."),
+            Self::RecursiveLock(_) => f.write_str("Recursive lock."),
+            Self::SyncLockForTooLong(_) => f.write_str("Synchronous lock for too long"),
+            Self::TooManyLocksHeld => f.write_str("Too many locks held."),
         }
     }
 }
@@ -85,3 +356,236 @@ impl fmt::Display for Error {
 }
 
 impl error::Error for Error {}
+
+#[cfg(all(test, feature = "backtrace", not(feature = "unchecked")))]
+#[tokio::test]
+async fn recursive_lock_backtrace() {
+    let (first, second) = crate::with_deadlock_check(
+        async {
+            let lock = crate::QueueRwLock::new((), "main_lock");
+            let _q = lock.queue().await.unwrap();
+
+            (
+                lock.queue().await.unwrap_err(),
+                lock.queue().await.unwrap_err(),
+            )
+        },
+        "lock_test".into(),
+    )
+    .await;
+
+    // carried by the errors themselves, out of their task.
+    assert_eq!(first, Error::RecursiveLock(Default::default()));
+    assert!(!std::ptr::eq(
+        first.backtrace().unwrap(),
+        second.backtrace().unwrap()
+    ));
+    assert!(Error::DeadlockDetected(Default::default())
+        .backtrace()
+        .is_none());
+    assert!(Error::Closed.backtrace().is_none());
+}
+
+#[cfg(all(test, feature = "telemetry", not(feature = "unchecked")))]
+#[test]
+fn recursive_lock_follows_from_holder_span() {
+    use parking_lot::Mutex;
+    use std::sync::{
+        atomic::{AtomicU64, Ordering::Relaxed},
+        Arc,
+    };
+    use tracing::{span, Event, Id, Metadata, Subscriber};
+
+    /// Records the spans, the current one, which are closed and which follow from which.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<RecorderState>);
+
+    #[derive(Default)]
+    struct RecorderState {
+        closed: Mutex<Vec<u64>>,
+        entered: Mutex<Vec<Id>>,
+        follows: Mutex<Vec<(u64, u64)>>,
+        metadata: Mutex<Vec<&'static Metadata<'static>>>,
+        refs: Mutex<Vec<usize>>,
+        next: AtomicU64,
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, attrs: &span::Attributes<'_>) -> Id {
+            self.0.metadata.lock().push(attrs.metadata());
+            self.0.refs.lock().push(1);
+            Id::from_u64(self.0.next.fetch_add(1, Relaxed) + 1)
+        }
+
+        fn record(&self, _: &Id, _: &span::Record<'_>) {}
+
+        fn record_follows_from(&self, span: &Id, follows: &Id) {
+            self.0
+                .follows
+                .lock()
+                .push((span.into_u64(), follows.into_u64()));
+        }
+
+        fn event(&self, _: &Event<'_>) {}
+
+        fn enter(&self, id: &Id) {
+            self.0.entered.lock().push(id.clone());
+        }
+
+        fn exit(&self, _: &Id) {
+            self.0.entered.lock().pop();
+        }
+
+        fn current_span(&self) -> tracing_core::span::Current {
+            match self.0.entered.lock().last() {
+                Some(id) => {
+                    let metadata = self.0.metadata.lock()[id.into_u64() as usize - 1];
+                    tracing_core::span::Current::new(id.clone(), metadata)
+                }
+                None => tracing_core::span::Current::none(),
+            }
+        }
+
+        fn clone_span(&self, id: &Id) -> Id {
+            self.0.refs.lock()[id.into_u64() as usize - 1] += 1;
+            id.clone()
+        }
+
+        fn try_close(&self, id: Id) -> bool {
+            let mut refs = self.0.refs.lock();
+            let count = &mut refs[id.into_u64() as usize - 1];
+
+            *count -= 1;
+
+            if *count == 0 {
+                self.0.closed.lock().push(id.into_u64());
+            }
+
+            *count == 0
+        }
+    }
+
+    let recorder = Recorder::default();
+    let state = &recorder.0;
+    let id = |name| {
+        let metadata = state.metadata.lock();
+        metadata.iter().position(|m| m.name() == name).unwrap() as u64 + 1
+    };
+
+    tracing::subscriber::with_default(recorder.clone(), || {
+        crate::block_on(crate::with_deadlock_check(
+            async {
+                let lock = crate::QueueRwLock::new((), "span_lock");
+                let _q = {
+                    let _holder = tracing::info_span!("holder").entered();
+                    lock.queue().await.unwrap()
+                };
+
+                assert_eq!(
+                    lock.queue().await.unwrap_err(),
+                    Error::RecursiveLock(Default::default())
+                );
+
+                // kept open by the lock held.
+                assert!(!state.closed.lock().contains(&id("holder")));
+            },
+            "lock_test".into(),
+        ))
+    });
+
+    let holder = id("holder");
+
+    assert!(state
+        .follows
+        .lock()
+        .contains(&(id("recursive lock"), holder)));
+    assert!(state.closed.lock().contains(&holder));
+}
+
+#[cfg(all(test, feature = "telemetry", not(feature = "unchecked")))]
+#[tokio::test]
+async fn sync_lock_for_too_long_logged_once() {
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+    use tracing::{
+        field::{Field, Visit},
+        span, Event, Id, Metadata, Subscriber,
+    };
+
+    /// Records the fields of each event.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<Vec<String>>>>);
+
+    struct Fields<'a>(&'a mut Vec<String>);
+
+    impl Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.push(format!("{}={value:?}", field.name()));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _: &span::Attributes<'_>) -> Id {
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _: &Id, _: &span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = Vec::new();
+            event.record(&mut Fields(&mut fields));
+            self.0.lock().push(fields);
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    let recorder = Recorder::default();
+    let _default = tracing::subscriber::set_default(recorder.clone());
+
+    let unheld = LockData::new("unheld_sync_lock");
+    Error::sync_lock_for_too_long(&unheld, "sync_lock");
+
+    crate::with_deadlock_check(
+        async {
+            let lock = crate::QueueRwLock::new((), "held_sync_lock");
+            let _q = lock.queue().await.unwrap();
+
+            Error::sync_lock_for_too_long(&lock.lock_data, "sync_lock");
+        },
+        "lock_test".into(),
+    )
+    .await;
+
+    let events = recorder.0.lock();
+    let field = |i: usize, name: &str| {
+        events[i]
+            .iter()
+            .find(|f| f.starts_with(&format!("{name}=")))
+            .cloned()
+    };
+
+    assert_eq!(events.len(), 2);
+    assert_eq!(
+        field(0, "lock").as_deref(),
+        Some("lock=\"unheld_sync_lock\"")
+    );
+    assert_eq!(field(0, "holder_task"), None);
+    assert_eq!(
+        field(1, "holder_task").as_deref(),
+        Some("holder_task=\"lock_test\"")
+    );
+    assert!(field(1, "held_ms").is_some());
+}
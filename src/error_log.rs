@@ -0,0 +1,70 @@
+use crate::clock;
+use parking_lot::Mutex;
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    time::{Duration, Instant},
+};
+
+type Windows = BTreeMap<(&'static str, &'static str), Window>;
+
+static WINDOWS: Mutex<Windows> = Mutex::new(BTreeMap::new());
+
+struct Window {
+    since: Instant,
+    suppressed: u64,
+}
+
+/// Returns the number of errors suppressed since the last log if the error
+/// `kind` raised on `lock` must be logged, `None` to suppress it.
+pub(crate) fn should_log(lock: &'static str, kind: &'static str) -> Option<u64> {
+    let interval = crate::Config::get().error_log_interval;
+    hit(&mut WINDOWS.lock(), (lock, kind), clock::now(), interval)
+}
+
+fn hit(
+    windows: &mut Windows,
+    key: (&'static str, &'static str),
+    now: Instant,
+    interval: Duration,
+) -> Option<u64> {
+    match windows.entry(key) {
+        Entry::Vacant(e) => {
+            e.insert(Window {
+                since: now,
+                suppressed: 0,
+            });
+
+            Some(0)
+        }
+        Entry::Occupied(mut e) => {
+            let window = e.get_mut();
+
+            if now.saturating_duration_since(window.since) >= interval {
+                window.since = now;
+                Some(std::mem::take(&mut window.suppressed))
+            } else {
+                window.suppressed += 1;
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn errors_are_summarized() {
+    let mut windows = Windows::new();
+    let key = ("main_lock", "recursive lock");
+    let interval = Duration::from_secs(10);
+    let now = Instant::now();
+
+    assert_eq!(hit(&mut windows, key, now, interval), Some(0));
+    assert_eq!(hit(&mut windows, key, now, interval), None);
+    assert_eq!(hit(&mut windows, key, now, interval), None);
+    assert_eq!(hit(&mut windows, ("other", key.1), now, interval), Some(0));
+    assert_eq!(hit(&mut windows, key, now + interval, interval), Some(2));
+    assert_eq!(
+        hit(&mut windows, key, now + interval, Duration::ZERO),
+        Some(0)
+    );
+}
@@ -0,0 +1,142 @@
+use crate::{
+    primitives::{task, LockData, Task},
+    sync::{async_mutex, mutex, rwlock},
+    Error, QueueRwLock, Result, VirtualLock,
+};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+static INTENTS: Mutex<Vec<Intent>> = Mutex::new(Vec::new());
+
+/// A lock of this crate which can be part of a [declare_intent].
+pub trait IntentLock {
+    #[doc(hidden)]
+    fn intent_key(&self) -> (u64, &'static str);
+}
+
+impl<T> IntentLock for QueueRwLock<T> {
+    fn intent_key(&self) -> (u64, &'static str) {
+        key(&self.lock_data)
+    }
+}
+
+impl<T> IntentLock for async_mutex::Mutex<T> {
+    fn intent_key(&self) -> (u64, &'static str) {
+        key(&self.lock_data)
+    }
+}
+
+impl<T> IntentLock for mutex::Mutex<T> {
+    fn intent_key(&self) -> (u64, &'static str) {
+        key(&self.lock_data)
+    }
+}
+
+impl<T> IntentLock for rwlock::RwLock<T> {
+    fn intent_key(&self) -> (u64, &'static str) {
+        key(&self.lock_data)
+    }
+}
+
+impl IntentLock for VirtualLock {
+    fn intent_key(&self) -> (u64, &'static str) {
+        key(&self.lock_data)
+    }
+}
+
+fn key(lock_data: &LockData) -> (u64, &'static str) {
+    (lock_data.id(), lock_data.name)
+}
+
+struct Intent {
+    id: u64,
+    locks: Vec<(u64, &'static str)>,
+    task: Arc<Task>,
+}
+
+/// Declares the locks the current section is about to take, in order.
+///
+/// Returns [Error::LockOrderConflict] if another task has declared two of
+/// these locks in the opposite order, before anything blocks. The intent
+/// is withdrawn when the returned guard is dropped.
+pub fn declare_intent(locks: &[&dyn IntentLock]) -> Result<IntentGuard> {
+    let task = task::current()?;
+    let locks: Vec<_> = locks.iter().map(|l| l.intent_key()).collect();
+
+    let mut intents = INTENTS.lock();
+
+    for other in intents.iter().filter(|i| !Arc::ptr_eq(&i.task, &task)) {
+        if let Some((a, b)) = inversion(&locks, &other.locks) {
+            return Err(Error::lock_order_conflict(a, b, &other.task.name()));
+        }
+    }
+
+    let id = crate::new_id();
+    intents.push(Intent { id, locks, task });
+
+    Ok(IntentGuard(id))
+}
+
+/// Finds two locks taken in opposite orders by `a` and `b`.
+fn inversion(
+    a: &[(u64, &'static str)],
+    b: &[(u64, &'static str)],
+) -> Option<(&'static str, &'static str)> {
+    let pos = |id| b.iter().position(|(b, _)| *b == id);
+
+    for (i, (first, first_name)) in a.iter().enumerate() {
+        let Some(first_pos) = pos(*first) else {
+            continue;
+        };
+
+        for (second, second_name) in &a[i + 1..] {
+            if pos(*second).is_some_and(|p| p < first_pos) {
+                return Some((first_name, second_name));
+            }
+        }
+    }
+
+    None
+}
+
+/// Keeps an intent declared with [declare_intent] until dropped.
+pub struct IntentGuard(u64);
+
+impl Drop for IntentGuard {
+    fn drop(&mut self) {
+        INTENTS.lock().retain(|i| i.id != self.0);
+    }
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn conflicting_intents() -> Result<()> {
+    use crate::with_deadlock_check;
+
+    let a = QueueRwLock::new((), "lock_a");
+    let b = QueueRwLock::new((), "lock_b");
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    let first = with_deadlock_check(
+        async {
+            let _intent = declare_intent(&[&a, &b])?;
+            rx.await.unwrap();
+            Ok(())
+        },
+        "first".into(),
+    );
+
+    let second = with_deadlock_check(
+        async {
+            let r = declare_intent(&[&b, &a]).err();
+            tx.send(()).unwrap();
+            assert_eq!(r, Some(Error::LockOrderConflict));
+            assert!(declare_intent(&[&a, &b]).is_ok());
+            Ok(())
+        },
+        "second".into(),
+    );
+
+    let (first, second) = tokio::join!(first, second);
+    first.and(second)
+}
@@ -0,0 +1,113 @@
+use crate::{sync::rwlock::RwLock, OnceVec, Result};
+use std::{collections::HashMap, future::Future, sync::Arc};
+
+/// A handle to a string interned in an [Interner].
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    /// The dense index of the symbol, starting at zero.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Deduplicates strings, handing out a [Symbol] for each distinct string.
+///
+/// Metadata of type `M` can be loaded asynchronously, once per symbol.
+pub struct Interner<M = ()> {
+    metadata: OnceVec<M>,
+
+    #[cfg_attr(not(feature = "telemetry"), allow(dead_code))]
+    name: &'static str,
+    strings: OnceVec<Arc<str>>,
+    symbols: RwLock<HashMap<Arc<str>, Symbol>>,
+}
+
+impl<M> Interner<M> {
+    /// Creates an empty interner which metrics are reported under `name`.
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            metadata: OnceVec::with_name(name),
+            name,
+            strings: OnceVec::with_name(name),
+            symbols: RwLock::new(HashMap::new(), name),
+        }
+    }
+
+    /// Returns the symbol of `s`, interning it if it is new.
+    ///
+    /// # Panics
+    ///
+    /// Panics if more than `u32::MAX` strings are interned.
+    pub fn intern(&self, s: &str) -> Result<Symbol> {
+        if let Some(symbol) = self.symbols.read()?.get(s) {
+            return Ok(*symbol);
+        }
+
+        let mut symbols = self.symbols.write()?;
+
+        if let Some(symbol) = symbols.get(s) {
+            return Ok(*symbol);
+        }
+
+        let s: Arc<str> = s.into();
+        let index = self.strings.push(Arc::clone(&s));
+        let symbol = Symbol(u32::try_from(index).expect("more than u32::MAX symbols"));
+
+        symbols.insert(s, symbol);
+
+        #[cfg(feature = "telemetry")]
+        metrics::gauge!("interner_len", "name" => self.name).set(symbols.len() as f64);
+
+        Ok(symbol)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Returns the metadata of `symbol`, loading it with `f` the first time.
+    pub async fn metadata<F>(&self, symbol: Symbol, f: F) -> Result<&M>
+    where
+        F: Future<Output = M>,
+    {
+        self.metadata.get_or_push(symbol.index(), f).await
+    }
+
+    /// Returns the string of `symbol`, or `None` if its index is past the
+    /// strings interned here.
+    ///
+    /// The symbols do not know their interner: a symbol of another interner
+    /// resolves to whichever string has its index, if any.
+    pub fn resolve(&self, symbol: Symbol) -> Option<&str> {
+        self.strings.get(symbol.index()).map(|s| &**s)
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn intern_and_resolve() -> Result<()> {
+    crate::with_deadlock_check(
+        async move {
+            let interner = Interner::new("interner");
+            let a = interner.intern("a")?;
+            let b = interner.intern("b")?;
+
+            assert_eq!(interner.intern("a")?, a);
+            assert_ne!(a, b);
+            assert_eq!(interner.resolve(b), Some("b"));
+
+            assert_eq!(*interner.metadata(b, async { 1 }).await?, 1);
+            assert_eq!(*interner.metadata(b, async { 2 }).await?, 1);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
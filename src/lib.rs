@@ -3,25 +3,69 @@ mod actix_web;
 
 mod async_load_rw_lock;
 mod async_once_cell;
+mod audit;
+pub mod blocking;
+mod budget;
+mod cache;
+mod clock;
+mod config;
 mod deadlock;
+mod dependency_graph;
 mod error;
 #[cfg(feature = "telemetry")]
+mod error_log;
+mod intent;
+mod interner;
+#[cfg(feature = "telemetry")]
 pub mod monitors;
+mod multi_guard;
+mod once_vec;
 mod primitives;
+mod proof;
 mod queue_rw_lock;
+mod shared_queue_rw_lock;
 pub mod sync;
+pub mod test_util;
+mod thresholds;
+mod throttled;
+#[cfg(feature = "tower")]
+mod tower;
 mod utils;
+mod virtual_lock;
+mod wait_graph;
 
 pub use async_load_rw_lock::*;
 pub use async_once_cell::*;
+pub use audit::AuditRecord;
+pub use budget::{BudgetExceeded, Budgeted};
+pub use cache::Cache;
+pub use clock::{set_clock, Clock, MockClock, SystemClock, TokioClock};
+pub use config::Config;
 #[cfg(feature = "telemetry")]
 pub use deadlock::warn_lock_held;
-pub use deadlock::with_deadlock_check;
-pub use error::Error;
+pub use deadlock::{
+    lock_free_section, rename_task, spawn_with_deadlock_check, strict_lock_free_section,
+    with_deadlock_check, DeadlockCheckHandle, LockFreeSection, LockStats,
+};
+pub use dependency_graph::{lock_dependency_graph, LockDependency};
+pub use error::{Error, Trace};
+pub use intent::{declare_intent, IntentGuard, IntentLock};
+pub use interner::{Interner, Symbol};
+pub use multi_guard::*;
+pub use once_vec::OnceVec;
+pub use proof::{ReadProof, WriteProof};
 pub use queue_rw_lock::*;
+pub use shared_queue_rw_lock::*;
+pub use thresholds::Thresholds;
+pub use throttled::Throttled;
 use utils::*;
+pub use virtual_lock::*;
+pub use wait_graph::generate_wait_graph_dot;
 
 #[cfg(feature = "actix_web_04")]
-pub use actix_web::DeadlockDetector;
+pub use actix_web::{block_with_deadlock_check, DeadlockDetector, TimingDeadlockDetector};
+
+#[cfg(feature = "tower")]
+pub use tower::{DeadlockCheck, DeadlockCheckLayer};
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,209 @@
+use crate::{
+    clock, Error, QueueRwLock, QueueRwLockQueueGuard, QueueRwLockReadGuard, QueueRwLockWriteGuard,
+    Thresholds,
+};
+use std::{
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    time::{Duration, Instant},
+};
+
+/// Guards of two locks acquired together and held as one critical section.
+///
+/// The guards are released in the reverse order of their acquisition and
+/// the "held for too long" warning is reported once for the whole section.
+pub struct MultiGuard<A, B> {
+    guards: ManuallyDrop<(A, B)>,
+    section: Section,
+}
+
+#[derive(Clone, Copy)]
+struct Section {
+    instant: Instant,
+
+    #[cfg_attr(not(feature = "telemetry"), allow(dead_code))]
+    names: [&'static str; 2],
+
+    #[cfg_attr(not(feature = "telemetry"), allow(dead_code))]
+    op: &'static str,
+
+    #[cfg_attr(not(feature = "telemetry"), allow(dead_code))]
+    thresholds: [Thresholds; 2],
+
+    /// The second guard has been acquired first.
+    swapped: bool,
+}
+
+impl<A, B> MultiGuard<A, B> {
+    /// The time elapsed since the first guard has been acquired.
+    pub fn elapsed(&self) -> Duration {
+        clock::elapsed(self.section.instant)
+    }
+
+    fn into_guards(self) -> ((A, B), Section) {
+        let mut this = ManuallyDrop::new(self);
+
+        // SAFETY: `this` is never dropped so the guards are taken only once.
+        let guards = unsafe { ManuallyDrop::take(&mut this.guards) };
+
+        (guards, this.section)
+    }
+}
+
+impl<'a, A, B> MultiGuard<QueueRwLockQueueGuard<'a, A>, QueueRwLockQueueGuard<'a, B>> {
+    /// Locks both locks with exclusive write access, in the same order
+    /// the queues have been acquired.
+    pub async fn write(
+        self,
+    ) -> Result<MultiGuard<QueueRwLockWriteGuard<'a, A>, QueueRwLockWriteGuard<'a, B>>, Error> {
+        let ((a, b), mut section) = self.into_guards();
+
+        let (mut a, mut b) = if section.swapped {
+            let b = b.write().await?;
+            (a.write().await?, b)
+        } else {
+            let a = a.write().await?;
+            (a, b.write().await?)
+        };
+
+        a.set_grouped();
+        b.set_grouped();
+        section.op = "write";
+
+        Ok(MultiGuard {
+            guards: ManuallyDrop::new((a, b)),
+            section,
+        })
+    }
+}
+
+impl<A, B> Deref for MultiGuard<A, B> {
+    type Target = (A, B);
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.guards
+    }
+}
+
+impl<A, B> DerefMut for MultiGuard<A, B> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guards
+    }
+}
+
+impl<A, B> Drop for MultiGuard<A, B> {
+    fn drop(&mut self) {
+        #[cfg(feature = "telemetry")]
+        {
+            let elapsed = self.elapsed();
+            let [a, b] = self.section.thresholds;
+            let op = self.section.op;
+
+            if elapsed > a.held(op).max(b.held(op)) {
+                let _ = tracing::warn_span!(
+                    "Locks held for too long",
+                    elapsed_secs = elapsed.as_secs(),
+                    names = ?self.section.names,
+                    op = op,
+                )
+                .entered();
+            }
+        }
+
+        // SAFETY: the guards are never used after this point.
+        let (a, b) = unsafe { ManuallyDrop::take(&mut self.guards) };
+
+        if self.section.swapped {
+            drop(a);
+            drop(b);
+        } else {
+            drop(b);
+            drop(a);
+        }
+    }
+}
+
+macro_rules! acquire_both {
+    ($a:ident, $b:ident, $op:ident) => {{
+        let swapped = $b.lock_data.id() < $a.lock_data.id();
+        let instant = clock::now();
+
+        let (mut a, mut b) = if swapped {
+            let b = $b.$op().await?;
+            ($a.$op().await?, b)
+        } else {
+            let a = $a.$op().await?;
+            (a, $b.$op().await?)
+        };
+
+        a.set_grouped();
+        b.set_grouped();
+
+        Ok(MultiGuard {
+            guards: ManuallyDrop::new((a, b)),
+            section: Section {
+                instant,
+                names: [$a.lock_data.name, $b.lock_data.name],
+                op: stringify!($op),
+                swapped,
+                thresholds: [$a.lock_data.thresholds, $b.lock_data.thresholds],
+            },
+        })
+    }};
+}
+
+/// Enqueues on both locks, always in the same order regardless of the
+/// order of the arguments.
+pub async fn queue_both<'a, A, B>(
+    a: &'a QueueRwLock<A>,
+    b: &'a QueueRwLock<B>,
+) -> Result<MultiGuard<QueueRwLockQueueGuard<'a, A>, QueueRwLockQueueGuard<'a, B>>, Error> {
+    acquire_both!(a, b, queue)
+}
+
+/// Locks both locks with shared read access, always in the same order
+/// regardless of the order of the arguments.
+pub async fn read_both<'a, A, B>(
+    a: &'a QueueRwLock<A>,
+    b: &'a QueueRwLock<B>,
+) -> Result<MultiGuard<QueueRwLockReadGuard<'a, A>, QueueRwLockReadGuard<'a, B>>, Error> {
+    acquire_both!(a, b, read)
+}
+
+/// Locks both locks with exclusive write access, always in the same order
+/// regardless of the order of the arguments.
+pub async fn write_both<'a, A, B>(
+    a: &'a QueueRwLock<A>,
+    b: &'a QueueRwLock<B>,
+) -> Result<MultiGuard<QueueRwLockWriteGuard<'a, A>, QueueRwLockWriteGuard<'a, B>>, Error> {
+    queue_both(a, b).await?.write().await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn write_both_any_order() -> Result<(), Error> {
+    use crate::test_util::held_locks;
+
+    crate::with_deadlock_check(
+        async move {
+            let a = QueueRwLock::new(1, "lock_a");
+            let b = QueueRwLock::new("b", "lock_b");
+
+            let mut w = write_both(&a, &b).await?;
+            *w.0 += 1;
+            *w.1 = "c";
+            drop(w);
+
+            assert!(held_locks().is_empty());
+
+            let r = read_both(&b, &a).await?;
+            assert_eq!((*r.0, *r.1), ("c", 2));
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
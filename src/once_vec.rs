@@ -0,0 +1,163 @@
+use crate::{
+    primitives::{LockAwaitGuard, LockData, LockHeldGuard},
+    timed_init, Result,
+};
+use once_cell::sync::OnceCell;
+use std::{
+    future::Future,
+    sync::atomic::{AtomicUsize, Ordering::Relaxed},
+};
+use tokio::sync::SetError;
+
+const BUCKETS: usize = usize::BITS as usize;
+
+type Bucket<T> = Box<[tokio::sync::OnceCell<T>]>;
+
+/// An append-only vector handing out references that stay valid while
+/// other elements are added.
+///
+/// The elements are stored in buckets doubling in size, which are never
+/// moved once allocated.
+pub struct OnceVec<T> {
+    buckets: [OnceCell<Bucket<T>>; BUCKETS],
+    len: AtomicUsize,
+    lock_data: LockData,
+}
+
+impl<T> OnceVec<T> {
+    pub const fn new() -> Self {
+        Self::with_name("once-vec")
+    }
+
+    /// Creates an empty vector which initializations are reported under
+    /// `name` in the telemetry.
+    pub const fn with_name(name: &'static str) -> Self {
+        Self {
+            buckets: [const { OnceCell::new() }; BUCKETS],
+            len: AtomicUsize::new(0),
+            lock_data: LockData::new(name),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.slot(index)?.get()
+    }
+
+    /// Returns the element at `index`, initializing it with `f` if it is
+    /// empty. The vector grows to include `index`.
+    ///
+    /// Concurrent calls for a same index wait for a single initialization.
+    ///
+    /// The bucket of `index` is allocated at once, with about as many slots
+    /// as `index`: a sparse index costs the memory of a vector that long.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is `usize::MAX`.
+    pub async fn get_or_push<F>(&self, index: usize, f: F) -> Result<&T>
+    where
+        F: Future<Output = T>,
+    {
+        let slot = self.slot_or_alloc(index);
+
+        self.len.fetch_max(index + 1, Relaxed);
+
+        if let Some(v) = slot.get() {
+            return Ok(v);
+        }
+
+        let wait = LockAwaitGuard::new(&self.lock_data, "once_init")?;
+
+        slot.get_or_try_init(|| async {
+            let _held = LockHeldGuard::new(wait)?;
+            Ok(timed_init(self.lock_data.name, f).await)
+        })
+        .await
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over the initialized elements, in order.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        (0..self.len()).filter_map(|index| self.get(index))
+    }
+
+    /// The number of elements, counting the ones being initialized.
+    pub fn len(&self) -> usize {
+        self.len.load(Relaxed)
+    }
+
+    /// Appends `value`, returning its index.
+    pub fn push(&self, mut value: T) -> usize {
+        loop {
+            let index = self.len.fetch_add(1, Relaxed);
+
+            // the slot may have been taken by a concurrent `get_or_push`.
+            match self.slot_or_alloc(index).set(value) {
+                Ok(()) => return index,
+                Err(SetError::AlreadyInitializedError(v) | SetError::InitializingError(v)) => {
+                    value = v
+                }
+            }
+        }
+    }
+
+    fn slot(&self, index: usize) -> Option<&tokio::sync::OnceCell<T>> {
+        let (bucket, offset) = location(index)?;
+        Some(&self.buckets[bucket].get()?[offset])
+    }
+
+    fn slot_or_alloc(&self, index: usize) -> &tokio::sync::OnceCell<T> {
+        let (bucket, offset) = location(index).expect("OnceVec index overflow");
+
+        let slots = self.buckets[bucket].get_or_init(|| {
+            (0..1usize << bucket)
+                .map(|_| tokio::sync::OnceCell::new())
+                .collect()
+        });
+
+        &slots[offset]
+    }
+}
+
+impl<T> Default for OnceVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The bucket and the offset inside the bucket of `index`, `None` for
+/// `usize::MAX` which no bucket holds.
+fn location(index: usize) -> Option<(usize, usize)> {
+    let n = index.checked_add(1)?;
+    let bucket = (usize::BITS - 1 - n.leading_zeros()) as usize;
+
+    Some((bucket, n - (1 << bucket)))
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn push_and_get_or_push() -> Result<()> {
+    crate::with_deadlock_check(
+        async move {
+            let vec = OnceVec::new();
+            let first = vec.get_or_push(0, async { 10 }).await?;
+
+            assert_eq!(vec.push(11), 1);
+            assert_eq!(*vec.get_or_push(0, async { 0 }).await?, 10);
+            assert_eq!(*vec.get_or_push(40, async { 50 }).await?, 50);
+            assert_eq!(vec.push(51), 41);
+
+            assert_eq!(*first, 10);
+            assert_eq!(vec.get(2), None);
+            assert_eq!(vec.get(usize::MAX), None);
+            assert_eq!(vec.iter().copied().collect::<Vec<_>>(), [10, 11, 50, 51]);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
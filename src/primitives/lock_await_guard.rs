@@ -6,7 +6,6 @@ pub(crate) struct LockAwaitGuard<'a> {
     #[cfg(feature = "telemetry")]
     gauge: metrics::Gauge,
 
-    #[cfg(feature = "telemetry")]
     instant: std::time::Instant,
 
     pub lock_data: &'a LockData,
@@ -20,23 +19,35 @@ impl<'a> LockAwaitGuard<'a> {
 
         let task = task::current()?;
 
+        task.check_lock_free(lock_data, op)?;
         task.set_await_lock_id(lock_data, op)?;
 
         #[cfg(feature = "telemetry")]
-        metrics::counter!("lock_await_counter", "name" => lock_data.name, "op" => op).increment(1);
+        metrics::counter!("lock_await_counter", "name" => lock_data.group, "op" => op).increment(1);
+
+        #[cfg(feature = "telemetry")]
+        for transaction in locks_held::names_with_op("transaction") {
+            tracing::warn!(
+                name = lock_data.name,
+                op = op,
+                transaction = transaction,
+                "Lock awaited while a transaction is open",
+            );
+        }
+
+        lock_data.add_waiter(op);
 
         Ok(Self {
             #[cfg(feature = "telemetry")]
             gauge: {
                 let gauge =
-                    metrics::gauge!("lock_await_gauge", "name" => lock_data.name, "op" => op);
+                    metrics::gauge!("lock_await_gauge", "name" => lock_data.group, "op" => op);
 
                 gauge.increment(1.0);
                 gauge
             },
 
-            #[cfg(feature = "telemetry")]
-            instant: std::time::Instant::now(),
+            instant: crate::clock::now(),
 
             lock_data,
             op,
@@ -45,12 +56,8 @@ impl<'a> LockAwaitGuard<'a> {
     }
 
     #[cfg(feature = "telemetry")]
-    fn drop_telemetry(&mut self) {
-        const LONG_WAIT: std::time::Duration = std::time::Duration::from_millis(500);
-
-        let elapsed = self.instant.elapsed();
-
-        if elapsed > LONG_WAIT {
+    fn drop_telemetry(&mut self, elapsed: std::time::Duration) {
+        if elapsed > self.lock_data.thresholds.wait() {
             tracing::warn!(
                 elapsed_ms = elapsed.as_millis(),
                 name = self.lock_data.name,
@@ -59,7 +66,7 @@ impl<'a> LockAwaitGuard<'a> {
             );
         }
 
-        metrics::counter!("lock_await_ms", "name" => self.lock_data.name, "op" => self.op)
+        metrics::counter!("lock_await_ms", "name" => self.lock_data.group, "op" => self.op)
             .increment(elapsed.as_millis() as u64);
 
         self.gauge.decrement(1.0);
@@ -68,9 +75,13 @@ impl<'a> LockAwaitGuard<'a> {
 
 impl Drop for LockAwaitGuard<'_> {
     fn drop(&mut self) {
+        let elapsed = crate::clock::elapsed(self.instant);
+
         #[cfg(feature = "telemetry")]
-        self.drop_telemetry();
+        self.drop_telemetry(elapsed);
 
+        self.task.stats.record_wait(elapsed);
         self.task.clear_await_lock_id();
+        self.lock_data.remove_waiter(self.op);
     }
 }
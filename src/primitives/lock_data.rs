@@ -1,47 +1,176 @@
-use super::Task;
-use crate::{new_id, Error, Result};
+#[cfg(not(feature = "unchecked"))]
+use super::{locks_held::LockHeld, Task};
+use crate::{new_id, Thresholds};
+#[cfg(not(feature = "unchecked"))]
+use crate::{Error, Result};
+#[cfg(not(feature = "unchecked"))]
 use parking_lot::Mutex;
-use std::sync::{
-    atomic::{AtomicU64, Ordering::Relaxed},
-    Arc,
-};
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+#[cfg(not(feature = "unchecked"))]
+use std::{sync::Arc, time::Instant};
 
 pub struct LockData {
-    locked_tasks: Mutex<Vec<Arc<Task>>>,
+    #[cfg(not(feature = "unchecked"))]
+    locked_tasks: Mutex<Vec<LockedTask>>,
     lock_id: AtomicU64,
 
-    #[cfg(feature = "telemetry")]
+    /// The name of the metrics, shared by the locks of a same group.
+    #[cfg_attr(not(feature = "telemetry"), allow(dead_code))]
+    pub group: &'static str,
+
     pub name: &'static str,
+
+    /// The lock is shared by counted permits, as a semaphore: the permits of
+    /// a task are counted in one entry of its `locks_held` and waiting is a
+    /// deadlock only if no other holder may release its permit.
+    pub permits: bool,
+
+    /// The reads of a task on this lock are counted in one entry of its
+    /// `locks_held` instead of failing as recursive.
+    pub recursive_reads: bool,
+
+    #[cfg_attr(not(feature = "telemetry"), allow(dead_code))]
+    pub thresholds: Thresholds,
+
+    /// The op of each `LockAwaitGuard` on this lock.
+    #[cfg(not(feature = "unchecked"))]
+    waiters: Mutex<Vec<&'static str>>,
 }
 
 impl LockData {
-    #[cfg_attr(not(feature = "telemetry"), allow(unused_variables))]
     pub const fn new(name: &'static str) -> Self {
         Self {
+            #[cfg(not(feature = "unchecked"))]
             locked_tasks: Mutex::new(Vec::new()),
             lock_id: AtomicU64::new(0),
-
-            #[cfg(feature = "telemetry")]
+            group: name,
             name,
+            permits: false,
+            recursive_reads: false,
+            thresholds: Thresholds::new(),
+            #[cfg(not(feature = "unchecked"))]
+            waiters: Mutex::new(Vec::new()),
         }
     }
 
-    pub fn add_task(&self, task: Arc<Task>) {
-        self.locked_tasks.lock().push(task);
+    #[cfg(not(feature = "unchecked"))]
+    pub fn add_task(&self, task: Arc<Task>, op: &'static str) {
+        task.add_held(self, op);
+
+        self.locked_tasks.lock().push(LockedTask {
+            op,
+            since: crate::clock::now(),
+
+            #[cfg(feature = "telemetry")]
+            span: tracing::Span::current(),
+
+            task,
+        });
     }
 
-    pub fn check_deadlock(&self, op: &str, locks_held: &[u64]) -> Result<()> {
+    /// Fails if a task holding this lock with an op blocking `op` awaits,
+    /// with a blocked op, one of the `locks_held` by the current task.
+    #[cfg(not(feature = "unchecked"))]
+    pub fn check_deadlock(&self, op: &str, locks_held: &[LockHeld]) -> Result<()> {
         for t in self.locked_tasks.lock().iter() {
-            let id = t.await_lock_id();
+            if !conflicts(op, t.op) {
+                continue;
+            }
+
+            let id = t.task.await_lock_id();
 
-            if id > 0 && locks_held.contains(&id) {
-                return Err(Error::deadlock_detected(self, op, &t.name));
+            if id == 0 {
+                continue;
+            }
+
+            let await_op = t.task.await_op();
+
+            if locks_held
+                .iter()
+                .any(|l| l.id == id && conflicts(await_op, l.op))
+            {
+                return Err(Error::deadlock_detected_by(self, op, t));
             }
         }
 
         Ok(())
     }
 
+    /// Same as [check_deadlock](Self::check_deadlock) for a lock shared by
+    /// [permits](Self::permits), all taken: fails if every other holder awaits,
+    /// with a blocked op, one of the `locks_held` by the current task, or if
+    /// the current task holds all the permits.
+    #[cfg(not(feature = "unchecked"))]
+    pub fn check_deadlock_permits(&self, op: &str, locks_held: &[LockHeld]) -> Result<()> {
+        let current = super::task::current()?;
+        let tasks = self.locked_tasks.lock();
+        let mut others = tasks
+            .iter()
+            .filter(|t| !Arc::ptr_eq(&t.task, &current))
+            .peekable();
+
+        let Some(&first) = others.peek() else {
+            return match tasks.is_empty() {
+                true => Ok(()),
+                false => Err(Error::deadlock_detected(self, op, &current.name())),
+            };
+        };
+
+        let blocked = |t: &LockedTask| {
+            let id = t.task.await_lock_id();
+
+            conflicts(op, t.op)
+                && id != 0
+                && locks_held
+                    .iter()
+                    .any(|l| l.id == id && conflicts(t.task.await_op(), l.op))
+        };
+
+        match others.all(blocked) {
+            true => Err(Error::deadlock_detected_by(self, op, first)),
+            false => Ok(()),
+        }
+    }
+
+    /// Calls `f` with the name of each task holding this lock and since when.
+    #[cfg(not(feature = "unchecked"))]
+    #[cfg(feature = "telemetry")]
+    pub fn for_each_holder<F>(&self, mut f: F)
+    where
+        F: FnMut(&str, Instant),
+    {
+        for t in self.locked_tasks.lock().iter() {
+            f(&t.task.name(), t.since);
+        }
+    }
+
+    #[cfg(all(feature = "telemetry", feature = "unchecked"))]
+    pub fn for_each_holder<F>(&self, _: F)
+    where
+        F: FnMut(&str, std::time::Instant),
+    {
+    }
+
+    /// The number of tasks holding this lock with `op`.
+    #[cfg(not(feature = "unchecked"))]
+    pub fn holders(&self, op: &str) -> usize {
+        self.locked_tasks
+            .lock()
+            .iter()
+            .filter(|t| t.op == op)
+            .count()
+    }
+
+    /// The span in which `task` has acquired this lock.
+    #[cfg(not(feature = "unchecked"))]
+    #[cfg(feature = "telemetry")]
+    pub fn holder_span(&self, task: &Arc<Task>) -> Option<tracing::Span> {
+        let tasks = self.locked_tasks.lock();
+        let t = tasks.iter().find(|t| Arc::ptr_eq(&t.task, task))?;
+
+        Some(t.span.clone())
+    }
+
     pub fn id(&self) -> u64 {
         let v = self.lock_id.load(Relaxed);
 
@@ -57,13 +186,124 @@ impl LockData {
         }
     }
 
+    #[cfg(not(feature = "unchecked"))]
+    pub fn add_waiter(&self, op: &'static str) {
+        self.waiters.lock().push(op);
+    }
+
+    #[cfg(not(feature = "unchecked"))]
+    pub fn remove_waiter(&self, op: &str) {
+        let mut waiters = self.waiters.lock();
+
+        if let Some(index) = waiters.iter().position(|w| *w == op) {
+            waiters.swap_remove(index);
+        }
+    }
+
+    /// The approximate number of tasks waiting for this lock.
+    #[cfg(not(feature = "unchecked"))]
+    pub fn waiters(&self) -> usize {
+        self.waiters.lock().len()
+    }
+
+    /// The approximate number of tasks waiting for this lock with `op`.
+    #[cfg(not(feature = "unchecked"))]
+    pub fn waiters_with_op(&self, op: &str) -> usize {
+        self.waiters.lock().iter().filter(|w| **w == op).count()
+    }
+
+    /// Changes the op with which `task` holds this lock.
+    #[cfg(not(feature = "unchecked"))]
+    pub fn set_task_op(&self, task: &Arc<Task>, old: &str, op: &'static str) {
+        task.set_held_op(self, old, op);
+
+        let mut tasks = self.locked_tasks.lock();
+
+        if let Some(t) = tasks
+            .iter_mut()
+            .find(|t| Arc::ptr_eq(&t.task, task) && t.op == old)
+        {
+            t.op = op;
+        }
+    }
+
+    #[cfg(not(feature = "unchecked"))]
     pub fn remove_task(&self, task: &Arc<Task>) {
+        task.remove_held(self);
+
         let mut tasks = self.locked_tasks.lock();
 
-        if let Some(idx) = tasks.iter().position(|t| Arc::ptr_eq(t, task)) {
+        if let Some(idx) = tasks.iter().position(|t| Arc::ptr_eq(&t.task, task)) {
             tasks.swap_remove(idx);
         } else {
             debug_assert!(false, "remove_task_not_found")
         }
     }
 }
+
+/// Whether acquiring a lock with `op` waits for a task holding it with `held`.
+///
+/// Reads are shared with other reads and with the queue, which only excludes
+/// other queues and writes. Any other op, or an unknown one, is exclusive.
+#[cfg(not(feature = "unchecked"))]
+fn conflicts(op: &str, held: &str) -> bool {
+    !matches!(
+        (op, held),
+        ("read", "read" | "queue") | ("queue", "read") | ("sync_read", "sync_read")
+    )
+}
+
+#[cfg(not(feature = "unchecked"))]
+pub(crate) struct LockedTask {
+    op: &'static str,
+
+    #[cfg_attr(not(feature = "telemetry"), allow(dead_code))]
+    since: Instant,
+
+    /// The span current when the lock has been acquired, kept open while
+    /// the lock is held so that the errors may follow from it.
+    #[cfg(feature = "telemetry")]
+    pub span: tracing::Span,
+
+    pub task: Arc<Task>,
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[test]
+fn check_deadlock_access_modes() {
+    let (l, m) = (LockData::new("l"), LockData::new("m"));
+    let holder = Arc::new(Task::new("holder".into()));
+
+    let held = |op| LockHeld {
+        count: 1,
+        id: m.id(),
+        name: m.name,
+        op,
+    };
+
+    let check = |holder_op, await_op, held_op, op| {
+        l.add_task(Arc::clone(&holder), holder_op);
+        holder.set_await_lock_id(&m, await_op).unwrap();
+
+        let r = l.check_deadlock(op, &[held(held_op)]);
+
+        holder.clear_await_lock_id();
+        l.remove_task(&holder);
+        r.is_err()
+    };
+
+    // read-read never conflicts.
+    assert!(!check("read", "read", "read", "read"));
+    assert!(!check("read", "write", "write", "read"));
+    assert!(!check("write", "read", "read", "read"));
+    assert!(!check("sync_read", "sync_read", "sync_read", "sync_read"));
+
+    // queue is shared with reads only.
+    assert!(!check("queue", "read", "queue", "read"));
+    assert!(!check("queue", "read", "read", "queue"));
+    assert!(check("queue", "queue", "queue", "queue"));
+    assert!(check("queue", "write", "read", "queue"));
+
+    assert!(check("write", "write", "read", "read"));
+    assert!(check("sync_lock", "sync_lock", "sync_lock", "sync_lock"));
+}
@@ -1,7 +1,7 @@
 use super::{locks_held, task, LockAwaitGuard, LockData, Task};
-use crate::Result;
+use crate::{clock, Config, Result};
 use std::{
-    sync::Arc,
+    sync::{atomic::Ordering::Relaxed, Arc},
     time::{Duration, Instant},
 };
 
@@ -9,12 +9,28 @@ pub(crate) struct LockHeldGuard<'a> {
     #[cfg(feature = "telemetry")]
     gauge: metrics::Gauge,
 
-    instant: Instant,
-    lock_data: &'a LockData,
+    #[cfg(feature = "telemetry")]
+    grouped: bool,
 
+    /// The gauge of the readers of the lock, for the ops holding a read.
     #[cfg(feature = "telemetry")]
+    readers: Option<metrics::Gauge>,
+
+    /// The lock has been detached from its task to be handed to another,
+    /// `task` being the `transfer` pseudo task holding it meanwhile.
+    detached: bool,
+
+    /// [Config::detect_sync_guard_across_await] read once when acquired.
+    detect_across_await: bool,
+
+    instant: Instant,
+    lock_data: &'a LockData,
     op: &'static str,
 
+    /// The polls of the task when the lock has been attributed to it,
+    /// truncated to keep the guards small.
+    polls: u32,
+
     task: Arc<Task>,
 }
 
@@ -26,73 +42,245 @@ impl<'a> LockHeldGuard<'a> {
     pub fn new_no_wait(lock_data: &'a LockData, op: &'static str) -> Result<Self> {
         let task = task::current()?;
 
+        task.check_lock_free(lock_data, op)?;
+
         Self::new_imp(lock_data, op, task)
     }
 
     #[cfg_attr(not(feature = "telemetry"), allow(unused_variables))]
     fn new_imp(lock_data: &'a LockData, op: &'static str, task: Arc<Task>) -> Result<Self> {
-        locks_held::add_lock(lock_data.id())?;
-        lock_data.add_task(Arc::clone(&task));
+        locks_held::add_lock(lock_data, op)?;
+        lock_data.add_task(Arc::clone(&task), op);
+        task.stats.record_acquired();
 
         #[cfg(feature = "telemetry")]
-        metrics::counter!("lock_held_counter", "name" => lock_data.name, "op" => op).increment(1);
+        metrics::counter!("lock_held_counter", "name" => lock_data.group, "op" => op).increment(1);
 
         Ok(Self {
-            instant: Instant::now(),
+            detached: false,
+            detect_across_await: Config::get().detect_sync_guard_across_await,
+            instant: clock::now(),
             lock_data,
+            polls: task.polls.load(Relaxed) as u32,
             task,
 
             #[cfg(feature = "telemetry")]
             gauge: {
                 let gauge =
-                    metrics::gauge!("lock_held_gauge", "name" => lock_data.name, "op" => op);
+                    metrics::gauge!("lock_held_gauge", "name" => lock_data.group, "op" => op);
 
                 gauge.increment(1.0);
                 gauge
             },
 
             #[cfg(feature = "telemetry")]
+            grouped: false,
+
+            #[cfg(feature = "telemetry")]
+            readers: match op {
+                "read" | "queue" => {
+                    let gauge = metrics::gauge!("lock_reader_gauge", "name" => lock_data.group);
+
+                    gauge.increment(1.0);
+                    Some(gauge)
+                }
+                _ => None,
+            },
+
             op,
         })
     }
 
     #[cfg(feature = "telemetry")]
-    fn drop_telemetry(&mut self) {
-        const LONG_LOCK: Duration = Duration::from_secs(30);
-
-        let elapsed = self.instant.elapsed();
-
-        if elapsed > LONG_LOCK {
+    fn drop_telemetry(&mut self, elapsed: Duration) {
+        if elapsed > self.lock_data.thresholds.held(self.op) && !self.grouped {
             let _ = tracing::warn_span!(
                 "Lock held for too long",
                 elapsed_secs = elapsed.as_secs(),
                 name = self.lock_data.name,
-                op = self.op
+                op = self.op,
+                task = %self.task.name(),
             )
             .entered();
         }
 
-        metrics::counter!("lock_held_ms", "name" => self.lock_data.name, "op" => self.op)
+        metrics::counter!("lock_held_ms", "name" => self.lock_data.group, "op" => self.op)
             .increment(elapsed.as_millis() as u64);
 
-        metrics::counter!("lock_release_counter", "name" => self.lock_data.name, "op" => self.op)
+        metrics::counter!("lock_release_counter", "name" => self.lock_data.group, "op" => self.op)
             .increment(1);
 
         self.gauge.decrement(1.0);
+
+        if let Some(readers) = &self.readers {
+            readers.decrement(1.0);
+        }
+    }
+
+    /// Attributes the lock to the current task, after a [detach](Self::detach),
+    /// measuring the hold from now.
+    pub fn attach(&mut self) -> Result<()> {
+        let task = task::current()?;
+
+        locks_held::add_lock(self.lock_data, self.op)?;
+        self.lock_data.remove_task(&self.task);
+        self.lock_data.add_task(Arc::clone(&task), self.op);
+
+        self.detached = false;
+        self.instant = clock::now();
+        self.polls = task.polls.load(Relaxed) as u32;
+        self.task = task;
+
+        Ok(())
+    }
+
+    /// Stops attributing the lock to its task, which may be finished by
+    /// the time the lock is [attached](Self::attach) to another task.
+    ///
+    /// The lock is attributed to a `transfer` pseudo task meanwhile, so the
+    /// detection and the wait graph still see it held.
+    pub fn detach(&mut self) {
+        if !self.detached {
+            self.task.stats.record_hold(self.elapsed());
+
+            let _ = locks_held::remove_lock(self.lock_data.id());
+            self.lock_data.remove_task(&self.task);
+
+            let transfer = Arc::new(Task::new("transfer".into()));
+
+            self.lock_data.add_task(Arc::clone(&transfer), self.op);
+            self.detached = true;
+            self.task = transfer;
+        }
+    }
+
+    /// Reports a guard of a sync lock dropped after its task has been
+    /// polled again, thus suspended while holding it.
+    fn check_across_await(&self) {
+        if !self.detect_across_await
+            || !self.op.starts_with("sync_")
+            || self.task.polls.load(Relaxed) as u32 == self.polls
+        {
+            return;
+        }
+
+        self.task.stats.record_sync_guard_across_await();
+
+        #[cfg(feature = "telemetry")]
+        {
+            tracing::warn!(
+                name = self.lock_data.name,
+                op = self.op,
+                task = %self.task.name(),
+                "Sync guard held across an await",
+            );
+
+            metrics::counter!("sync_guard_across_await_counter", "name" => self.lock_data.group, "op" => self.op)
+                .increment(1);
+        }
     }
 
     pub fn elapsed(&self) -> Duration {
-        self.instant.elapsed()
+        clock::elapsed(self.instant)
+    }
+
+    /// Changes the op of the lock held in place, such as a read turned into
+    /// a queue, without tracking a second acquisition.
+    pub fn set_op(&mut self, op: &'static str) {
+        if !self.detached {
+            let _ = locks_held::set_op(self.lock_data.id(), self.op, op);
+            self.lock_data.set_task_op(&self.task, self.op, op);
+        }
+
+        #[cfg(feature = "telemetry")]
+        {
+            self.gauge.decrement(1.0);
+            self.gauge =
+                metrics::gauge!("lock_held_gauge", "name" => self.lock_data.group, "op" => op);
+            self.gauge.increment(1.0);
+
+            match (op, &self.readers) {
+                ("read" | "queue", None) => {
+                    let readers =
+                        metrics::gauge!("lock_reader_gauge", "name" => self.lock_data.group);
+
+                    readers.increment(1.0);
+                    self.readers = Some(readers);
+                }
+                ("read" | "queue", Some(_)) => {}
+                (_, readers) => {
+                    if let Some(readers) = readers {
+                        readers.decrement(1.0);
+                    }
+
+                    self.readers = None;
+                }
+            }
+        }
+
+        self.op = op;
+    }
+
+    /// Leaves the "held for too long" warning to the `MultiGuard` owning this guard.
+    pub fn set_grouped(&mut self) {
+        #[cfg(feature = "telemetry")]
+        {
+            self.grouped = true;
+        }
     }
 }
 
 impl Drop for LockHeldGuard<'_> {
     fn drop(&mut self) {
+        let elapsed = self.elapsed();
+
         #[cfg(feature = "telemetry")]
-        self.drop_telemetry();
+        self.drop_telemetry(elapsed);
+
+        if !self.detached {
+            self.check_across_await();
+            self.task.stats.record_hold(elapsed);
 
-        let _ = locks_held::remove_lock(self.lock_data.id());
+            let _ = locks_held::remove_lock(self.lock_data.id());
+        }
 
         self.lock_data.remove_task(&self.task);
     }
 }
+
+#[cfg(test)]
+#[tokio::test]
+async fn sync_guard_across_await() -> Result<()> {
+    use crate::sync::mutex::Mutex;
+
+    let _config = Config {
+        detect_sync_guard_across_await: true,
+        ..Config::new()
+    }
+    .scoped();
+
+    crate::with_deadlock_check(
+        async {
+            let lock = Mutex::new(0, "sync_lock");
+
+            drop(lock.lock()?);
+            assert_eq!(
+                task::current()?.stats.lock_stats().sync_guards_across_await,
+                0
+            );
+
+            let guard = lock.lock()?;
+            tokio::task::yield_now().await;
+            drop(guard);
+
+            assert_eq!(
+                task::current()?.stats.lock_stats().sync_guards_across_await,
+                1
+            );
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
@@ -1,21 +1,104 @@
+//! The locks held by the current task. With the `unchecked` feature, no lock
+//! is held by any task.
+
+#[cfg(not(feature = "unchecked"))]
 use super::LockData;
-use crate::{Error, Result};
+#[cfg(not(feature = "unchecked"))]
+use crate::{Config, Error, Result};
+#[cfg(not(feature = "unchecked"))]
 use std::{cell::RefCell, convert::identity, future::Future};
+#[cfg(not(feature = "unchecked"))]
 use tokio::{task::futures::TaskLocalFuture, task_local};
 
+#[cfg(not(feature = "unchecked"))]
 task_local! {
-    static LOCKS_HELD: RefCell<Vec<u64>>;
+    static LOCKS_HELD: RefCell<Vec<LockHeld>>;
 }
 
-pub(crate) fn add_lock(lock_id: u64) -> Result<()> {
-    debug_assert_ne!(lock_id, 0);
+#[cfg(not(feature = "unchecked"))]
+pub(crate) struct LockHeld {
+    /// The acquisitions tracked by this entry, above 1 for the recursive reads and the permits.
+    pub count: usize,
 
-    try_with(|locks_held| locks_held.push(lock_id))
+    pub id: u64,
+    pub name: &'static str,
+    pub op: &'static str,
 }
 
+#[cfg(not(feature = "unchecked"))]
+pub(crate) fn add_lock(lock_data: &LockData, op: &'static str) -> Result<()> {
+    let id = lock_data.id();
+
+    debug_assert_ne!(id, 0);
+
+    try_with(|locks_held| {
+        if let Some(held) = counted_entry(lock_data, op, locks_held) {
+            held.count += 1;
+            return Ok(());
+        }
+
+        check_limit(lock_data, op, locks_held, Config::get().max_locks_held)?;
+
+        if !locks_held.is_empty() {
+            let _ = super::task::try_with(|task| task.edges.record(locks_held, lock_data.name));
+        }
+
+        locks_held.push(LockHeld {
+            count: 1,
+            id,
+            name: lock_data.name,
+            op,
+        });
+
+        Ok(())
+    })
+    .and_then(identity)
+}
+
+/// The entry already held by the task counting this acquisition, for the
+/// permits and the reads of a lock allowing the recursive reads.
+#[cfg(not(feature = "unchecked"))]
+fn counted_entry<'a>(
+    lock_data: &LockData,
+    op: &str,
+    locks_held: &'a mut [LockHeld],
+) -> Option<&'a mut LockHeld> {
+    if lock_data.permits {
+        return locks_held.iter_mut().find(|l| l.id == lock_data.id());
+    }
+
+    if !lock_data.recursive_reads || op != "read" {
+        return None;
+    }
+
+    locks_held
+        .iter_mut()
+        .find(|l| l.id == lock_data.id() && l.op == "read")
+}
+
+#[cfg(not(feature = "unchecked"))]
+fn check_limit(
+    lock_data: &LockData,
+    op: &str,
+    locks_held: &[LockHeld],
+    max: Option<usize>,
+) -> Result<()> {
+    match max {
+        Some(max) if locks_held.len() >= max => {
+            Err(Error::too_many_locks_held(lock_data, op, locks_held))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(feature = "unchecked"))]
 pub(crate) fn check_deadlock(lock_data: &LockData, op: &str) -> Result<()> {
     try_with(|locks_held| {
-        if locks_held.contains(&lock_data.id()) {
+        if lock_data.permits {
+            return lock_data.check_deadlock_permits(op, locks_held);
+        }
+
+        if locks_held.iter().any(|l| l.id == lock_data.id()) {
             return Err(Error::recursive_lock(lock_data, op));
         }
 
@@ -24,31 +107,120 @@ pub(crate) fn check_deadlock(lock_data: &LockData, op: &str) -> Result<()> {
     .and_then(identity)
 }
 
-#[cfg(any(test, feature = "telemetry"))]
+#[cfg(all(any(test, feature = "telemetry"), not(feature = "unchecked")))]
 pub(crate) fn has_lock_held() -> bool {
     try_with(|l| !l.is_empty()).unwrap_or_default()
 }
 
+#[cfg(all(feature = "telemetry", feature = "unchecked"))]
+pub(crate) fn has_lock_held() -> bool {
+    false
+}
+
+/// Returns the names of the locks held with the `op`.
+#[cfg(all(feature = "telemetry", not(feature = "unchecked")))]
+pub(crate) fn names_with_op(op: &str) -> Vec<&'static str> {
+    try_with(|l| l.iter().filter(|l| l.op == op).map(|l| l.name).collect()).unwrap_or_default()
+}
+
+#[cfg(not(feature = "unchecked"))]
+pub(crate) fn names() -> Vec<&'static str> {
+    try_with(|l| l.iter().map(|l| l.name).collect()).unwrap_or_default()
+}
+
+#[cfg(feature = "unchecked")]
+pub(crate) fn names() -> Vec<&'static str> {
+    Vec::new()
+}
+
+#[cfg(not(feature = "unchecked"))]
 pub(crate) fn remove_lock(lock_id: u64) -> Result<()> {
     try_with(|locks_held| {
-        if let Some(idx) = locks_held.iter().position(|p| *p == lock_id) {
-            locks_held.swap_remove(idx);
+        if let Some(idx) = locks_held.iter().position(|p| p.id == lock_id) {
+            match &mut locks_held[idx].count {
+                count @ 2.. => *count -= 1,
+                _ => {
+                    locks_held.swap_remove(idx);
+                }
+            }
         }
     })
 }
 
-pub(crate) fn scope<F>(f: F) -> TaskLocalFuture<RefCell<Vec<u64>>, F>
+/// Changes the op of an entry of the lock, splitting it from the other
+/// acquisitions it counts.
+#[cfg(not(feature = "unchecked"))]
+pub(crate) fn set_op(lock_id: u64, old: &str, op: &'static str) -> Result<()> {
+    try_with(|locks_held| {
+        let Some(held) = locks_held
+            .iter_mut()
+            .find(|l| l.id == lock_id && l.op == old)
+        else {
+            return;
+        };
+
+        if held.count == 1 {
+            held.op = op;
+            return;
+        }
+
+        held.count -= 1;
+
+        let held = LockHeld {
+            count: 1,
+            id: lock_id,
+            name: held.name,
+            op,
+        };
+
+        locks_held.push(held);
+    })
+}
+
+#[cfg(not(feature = "unchecked"))]
+pub(crate) fn scope<F>(f: F) -> TaskLocalFuture<RefCell<Vec<LockHeld>>, F>
 where
     F: Future,
 {
     LOCKS_HELD.scope(RefCell::new(Vec::new()), f)
 }
 
+#[cfg(not(feature = "unchecked"))]
+pub(crate) fn sync_scope<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    LOCKS_HELD.sync_scope(RefCell::new(Vec::new()), f)
+}
+
+#[cfg(not(feature = "unchecked"))]
 fn try_with<F, R>(f: F) -> Result<R>
 where
-    F: FnOnce(&mut Vec<u64>) -> R,
+    F: FnOnce(&mut Vec<LockHeld>) -> R,
 {
     LOCKS_HELD
         .try_with(|cell| f(&mut cell.borrow_mut()))
         .map_err(Error::not_deadlock_check_future)
 }
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[test]
+fn limit_of_locks_held() {
+    let lock_data = LockData::new("main_lock");
+
+    let held = |name| LockHeld {
+        count: 1,
+        id: 1,
+        name,
+        op: "read",
+    };
+
+    let locks_held = [held("a"), held("b")];
+
+    assert!(check_limit(&lock_data, "read", &locks_held, None).is_ok());
+    assert!(check_limit(&lock_data, "read", &locks_held, Some(3)).is_ok());
+    assert_eq!(
+        check_limit(&lock_data, "read", &locks_held, Some(2)),
+        Err(Error::TooManyLocksHeld)
+    );
+}
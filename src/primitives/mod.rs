@@ -1,10 +1,24 @@
+#[cfg(not(feature = "unchecked"))]
 mod lock_await_guard;
 mod lock_data;
+#[cfg(not(feature = "unchecked"))]
 mod lock_held_guard;
 pub(crate) mod locks_held;
+mod optimistic;
+mod snapshot;
 pub(crate) mod task;
+#[cfg(feature = "unchecked")]
+mod unchecked;
 
+#[cfg(not(feature = "unchecked"))]
 pub(crate) use lock_await_guard::LockAwaitGuard;
 pub(crate) use lock_data::LockData;
+#[cfg(not(feature = "unchecked"))]
+pub(crate) use lock_data::LockedTask;
+#[cfg(not(feature = "unchecked"))]
 pub(crate) use lock_held_guard::LockHeldGuard;
+pub(crate) use optimistic::Optimistic;
+pub(crate) use snapshot::Snapshot;
 pub(crate) use task::Task;
+#[cfg(feature = "unchecked")]
+pub(crate) use unchecked::{LockAwaitGuard, LockHeldGuard};
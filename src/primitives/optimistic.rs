@@ -0,0 +1,108 @@
+use bytemuck::Pod;
+use std::{
+    hint::spin_loop,
+    mem::size_of,
+    sync::atomic::{fence, AtomicBool, AtomicU64, Ordering},
+};
+
+const WORD: usize = size_of::<u64>();
+
+/// A copy of the value of a lock, published at the end of each write and
+/// readable without taking the lock, retrying while a write is publishing.
+///
+/// The value is stored as its bytes in atomic words, so a read racing with
+/// a publish copies stale words instead of reading memory being written.
+pub(crate) struct Optimistic<T> {
+    /// The value has been changed without being published.
+    dirty: AtomicBool,
+    publish: fn(&Self, &T),
+    seq: AtomicU64,
+    words: Box<[AtomicU64]>,
+}
+
+impl<T> Optimistic<T> {
+    pub fn new(value: &T) -> Self
+    where
+        T: Pod,
+    {
+        let words = (0..size_of::<T>().div_ceil(WORD))
+            .map(|_| AtomicU64::new(0))
+            .collect();
+
+        let this = Self {
+            dirty: AtomicBool::new(false),
+            publish: Self::write,
+            seq: AtomicU64::new(0),
+            words,
+        };
+
+        this.write(value);
+        this
+    }
+
+    /// Publishes `value`. The caller must have an exclusive access to the lock.
+    pub fn publish(&self, value: &T) {
+        (self.publish)(self, value)
+    }
+
+    /// Returns the published value, or `None` if it may be outdated.
+    pub fn read(&self) -> Option<T>
+    where
+        T: Pod,
+    {
+        loop {
+            if self.dirty.load(Ordering::Acquire) {
+                return None;
+            }
+
+            let seq = self.seq.load(Ordering::Acquire);
+
+            if seq & 1 == 1 {
+                spin_loop();
+                continue;
+            }
+
+            let mut value = T::zeroed();
+            let bytes = bytemuck::bytes_of_mut(&mut value);
+
+            for (chunk, word) in bytes.chunks_mut(WORD).zip(self.words.iter()) {
+                let word = word.load(Ordering::Relaxed).to_ne_bytes();
+                chunk.copy_from_slice(&word[..chunk.len()]);
+            }
+
+            fence(Ordering::Acquire);
+
+            if self.seq.load(Ordering::Relaxed) == seq {
+                return Some(value);
+            }
+        }
+    }
+
+    /// Marks the value as changed by a path which does not publish.
+    pub fn set_dirty(&self) {
+        self.dirty.store(true, Ordering::Release);
+    }
+
+    fn write(&self, value: &T)
+    where
+        T: Pod,
+    {
+        let seq = self.seq.load(Ordering::Relaxed);
+
+        self.seq.store(seq + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        for (chunk, word) in bytemuck::bytes_of(value)
+            .chunks(WORD)
+            .zip(self.words.iter())
+        {
+            let mut bytes = [0; WORD];
+
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            word.store(u64::from_ne_bytes(bytes), Ordering::Relaxed);
+        }
+
+        self.seq.store(seq + 2, Ordering::Release);
+        self.dirty.store(false, Ordering::Release);
+    }
+}
@@ -0,0 +1,38 @@
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+/// An `Arc` of the value of a lock, replaced at the end of each write, so
+/// the readers share it without taking the lock.
+pub(crate) struct Snapshot<T> {
+    clone: fn(&T) -> T,
+
+    /// `None` while the value has been changed without being published.
+    value: RwLock<Option<Arc<T>>>,
+}
+
+impl<T> Snapshot<T> {
+    pub fn new(value: &T) -> Self
+    where
+        T: Clone,
+    {
+        Self {
+            clone: T::clone,
+            value: RwLock::new(Some(Arc::new(value.clone()))),
+        }
+    }
+
+    /// Publishes a copy of `value`. The caller must have an exclusive access to the lock.
+    pub fn publish(&self, value: &T) {
+        *self.value.write() = Some(Arc::new((self.clone)(value)));
+    }
+
+    /// Returns the published value, or `None` if it may be outdated.
+    pub fn get(&self) -> Option<Arc<T>> {
+        self.value.read().clone()
+    }
+
+    /// Marks the value as changed by a path which does not publish.
+    pub fn set_dirty(&mut self) {
+        *self.value.get_mut() = None;
+    }
+}
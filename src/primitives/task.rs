@@ -1,21 +1,133 @@
+#[cfg(not(feature = "unchecked"))]
 use super::LockData;
-use crate::{Error, Result};
+use crate::{dependency_graph::TaskEdges, Error, LockStats, Result};
+#[cfg(not(feature = "unchecked"))]
+use std::future::Future;
 use std::{
-    future::Future,
+    cell::Cell,
+    collections::VecDeque,
     sync::{
-        atomic::{AtomicU64, Ordering::Relaxed},
-        Arc,
+        atomic::{AtomicU32, AtomicU64, Ordering::Relaxed},
+        Arc, Weak,
     },
+    time::Duration,
 };
-use tokio::{task::futures::TaskLocalFuture, task_local};
+#[cfg(not(feature = "unchecked"))]
+use tokio::task::futures::TaskLocalFuture;
+use tokio::task_local;
+
+const SHARDS: usize = 16;
+
+/// The tasks which have entered a scope, for [generate_wait_graph_dot](crate::generate_wait_graph_dot),
+/// sharded by thread so that the scopes entered concurrently rarely contend.
+static TASKS: [parking_lot::Mutex<Vec<Weak<Task>>>; SHARDS] =
+    [const { parking_lot::Mutex::new(Vec::new()) }; SHARDS];
 
 pub(crate) struct Task {
     pub await_lock_id: AtomicU64,
-    pub name: String,
+
+    /// The name and op of the lock awaited, empty while unknown.
+    awaiting: parking_lot::Mutex<(&'static str, &'static str)>,
+
+    /// The locks acquired while holding others, for the lock dependency graph.
+    pub edges: TaskEdges,
+
+    /// The id, name and op of the locks attributed to the task.
+    held: parking_lot::Mutex<Vec<(u64, &'static str, &'static str)>>,
+
+    pub lock_free: AtomicU32,
+    pub lock_free_strict: AtomicU32,
+
+    /// Overridden by [rename_task](crate::rename_task) while its future is polled.
+    name: parking_lot::Mutex<Arc<str>>,
+
+    /// The number of times the future of the task has been polled.
+    #[cfg(not(feature = "unchecked"))]
+    pub polls: AtomicU64,
+
+    pub stats: TaskStats,
 }
 
 impl Task {
+    pub fn new(name: String) -> Self {
+        Self {
+            await_lock_id: AtomicU64::new(0),
+            awaiting: parking_lot::Mutex::new(("", "")),
+
+            edges: TaskEdges::default(),
+
+            held: Default::default(),
+            lock_free: AtomicU32::new(0),
+            lock_free_strict: AtomicU32::new(0),
+            name: parking_lot::Mutex::new(name.into()),
+            #[cfg(not(feature = "unchecked"))]
+            polls: AtomicU64::new(0),
+            stats: TaskStats::default(),
+        }
+    }
+}
+
+impl Task {
+    /// Fails if the task is inside a lock free section.
+    #[cfg_attr(not(feature = "telemetry"), allow(unused_variables))]
+    #[cfg(not(feature = "unchecked"))]
+    pub fn check_lock_free(&self, lock_data: &LockData, op: &str) -> Result<()> {
+        if self.lock_free_strict.load(Relaxed) > 0 {
+            panic!(
+                "{} acquired with {op} inside a lock free section",
+                lock_data.name
+            );
+        }
+
+        if self.lock_free.load(Relaxed) > 0 {
+            return Err(Error::lock_free_section(lock_data, op, &self.name()));
+        }
+
+        Ok(())
+    }
+
+    pub fn name(&self) -> Arc<str> {
+        Arc::clone(&self.name.lock())
+    }
+
+    /// Replaces the name of the task, returning the previous one.
+    pub fn replace_name(&self, name: Arc<str>) -> Arc<str> {
+        std::mem::replace(&mut self.name.lock(), name)
+    }
+
+    #[cfg(not(feature = "unchecked"))]
+    pub fn add_held(&self, lock_data: &LockData, op: &'static str) {
+        self.held.lock().push((lock_data.id(), lock_data.name, op));
+    }
+
+    #[cfg(not(feature = "unchecked"))]
+    pub fn set_held_op(&self, lock_data: &LockData, old: &str, op: &'static str) {
+        let mut held = self.held.lock();
+
+        if let Some(h) = held
+            .iter_mut()
+            .find(|h| h.0 == lock_data.id() && h.2 == old)
+        {
+            h.2 = op;
+        }
+    }
+
+    pub fn held(&self) -> Vec<(u64, &'static str, &'static str)> {
+        self.held.lock().clone()
+    }
+
+    #[cfg(not(feature = "unchecked"))]
+    pub fn remove_held(&self, lock_data: &LockData) {
+        let mut held = self.held.lock();
+
+        if let Some(idx) = held.iter().position(|h| h.0 == lock_data.id()) {
+            held.swap_remove(idx);
+        }
+    }
+
+    #[cfg(not(feature = "unchecked"))]
     pub fn clear_await_lock_id(&self) {
+        *self.awaiting.lock() = ("", "");
         self.await_lock_id.store(0, Relaxed);
     }
 
@@ -23,13 +135,32 @@ impl Task {
         self.await_lock_id.load(Relaxed)
     }
 
-    pub fn set_await_lock_id(&self, lock_data: &LockData, op: &str) -> Result<()> {
+    #[cfg(not(feature = "unchecked"))]
+    pub fn await_op(&self) -> &'static str {
+        self.awaiting.lock().1
+    }
+
+    /// The id, name and op of the lock awaited, if any.
+    pub fn awaiting(&self) -> Option<(u64, &'static str, &'static str)> {
+        let (name, op) = *self.awaiting.lock();
+
+        match self.await_lock_id() {
+            0 => None,
+            id => Some((id, name, op)),
+        }
+    }
+
+    #[cfg(not(feature = "unchecked"))]
+    pub fn set_await_lock_id(&self, lock_data: &LockData, op: &'static str) -> Result<()> {
         match self
             .await_lock_id
             .compare_exchange(0, lock_data.id(), Relaxed, Relaxed)
         {
-            Ok(_) => Ok(()),
-            Err(_) => Err(Error::deadlock_detected(lock_data, op, &self.name)),
+            Ok(_) => {
+                *self.awaiting.lock() = (lock_data.name, op);
+                Ok(())
+            }
+            Err(_) => Err(Error::deadlock_detected(lock_data, op, &self.name())),
         }
     }
 }
@@ -38,17 +169,87 @@ pub(crate) fn current() -> Result<Arc<Task>> {
     try_with(Arc::clone)
 }
 
-pub(crate) fn scope<F>(f: F, task_name: String) -> TaskLocalFuture<Arc<Task>, F>
+/// The tasks still alive which have entered a scope.
+pub(crate) fn live() -> Vec<Arc<Task>> {
+    let mut tasks = Vec::new();
+
+    for shard in &TASKS {
+        tasks.extend(shard.lock().iter().filter_map(Weak::upgrade));
+    }
+
+    // a task may enter several scopes.
+    tasks.sort_by_key(Arc::as_ptr);
+    tasks.dedup_by(|a, b| Arc::ptr_eq(a, b));
+    tasks
+}
+
+#[cfg(not(feature = "unchecked"))]
+fn register(task: &Arc<Task>) {
+    let mut tasks = TASKS[SHARD.with(|shard| *shard)].lock();
+
+    // drops the finished tasks as the list grows.
+    if tasks.len() == tasks.capacity() {
+        tasks.retain(|t| t.strong_count() > 0);
+    }
+
+    tasks.push(Arc::downgrade(task));
+}
+
+#[cfg(not(feature = "unchecked"))]
+pub(crate) fn scope<F>(f: F, task: Arc<Task>) -> TaskLocalFuture<Arc<Task>, F>
 where
     F: Future,
 {
-    TASK.scope(
-        Arc::new(Task {
-            await_lock_id: AtomicU64::new(0),
-            name: task_name,
-        }),
-        f,
-    )
+    register(&task);
+    TASK.scope(task, f)
+}
+
+#[cfg(not(feature = "unchecked"))]
+pub(crate) fn sync_scope<F, R>(f: F, task: Arc<Task>) -> R
+where
+    F: FnOnce() -> R,
+{
+    register(&task);
+
+    let _restore = RestoreSyncScope(IN_SYNC_SCOPE.replace(true));
+    TASK.sync_scope(task, f)
+}
+
+/// Returns true when running inside a `with_deadlock_check` future, where
+/// blocking the thread is not allowed.
+pub(crate) fn is_async() -> bool {
+    TASK.try_with(|_| ()).is_ok() && !IN_SYNC_SCOPE.get()
+}
+
+/// Returns true on a thread of a tokio runtime, inside a `with_deadlock_check`
+/// future or not, unless running a [sync_scope].
+#[cfg(not(feature = "unchecked"))]
+pub(crate) fn on_runtime_thread() -> bool {
+    !IN_SYNC_SCOPE.get()
+        && (TASK.try_with(|_| ()).is_ok() || tokio::runtime::Handle::try_current().is_ok())
+}
+
+#[cfg(not(feature = "unchecked"))]
+struct RestoreSyncScope(bool);
+
+#[cfg(not(feature = "unchecked"))]
+impl Drop for RestoreSyncScope {
+    fn drop(&mut self) {
+        IN_SYNC_SCOPE.set(self.0);
+    }
+}
+
+thread_local! {
+    /// Set while running a [sync_scope], telling it apart from a [scope].
+    static IN_SYNC_SCOPE: Cell<bool> = const { Cell::new(false) };
+
+    /// The shard of [TASKS] in which the thread registers the tasks.
+    #[cfg(not(feature = "unchecked"))]
+    static SHARD: usize = {
+        static NEXT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+        NEXT.fetch_add(1, Relaxed) % SHARDS
+    };
 }
 
 pub(crate) fn try_with<F, R>(f: F) -> Result<R>
@@ -61,3 +262,76 @@ where
 task_local! {
     static TASK: Arc<Task>;
 }
+
+/// The number of last errors kept in the statistics of a task.
+pub(crate) const ERRORS_KEPT: usize = 16;
+
+/// Totals of the lock timings of a task.
+#[derive(Default)]
+pub(crate) struct TaskStats {
+    acquired: AtomicU64,
+    errors: AtomicU64,
+
+    /// The last [ERRORS_KEPT] errors, the oldest first.
+    last_errors: parking_lot::Mutex<VecDeque<Error>>,
+    hold_us: AtomicU64,
+    max_hold_us: AtomicU64,
+    sync_guards_across_await: AtomicU64,
+    wait_us: AtomicU64,
+}
+
+impl TaskStats {
+    pub fn lock_stats(&self) -> LockStats {
+        LockStats {
+            acquired: self.acquired.load(Relaxed),
+            errors: self.errors.load(Relaxed),
+            last_errors: self.last_errors.lock().iter().cloned().collect(),
+            max_hold: Duration::from_micros(self.max_hold_us.load(Relaxed)),
+            sync_guards_across_await: self.sync_guards_across_await.load(Relaxed),
+            wait: self.wait(),
+        }
+    }
+
+    #[cfg(not(feature = "unchecked"))]
+    pub fn record_acquired(&self) {
+        self.acquired.fetch_add(1, Relaxed);
+    }
+
+    pub fn record_error(&self, e: Error) {
+        let mut last = self.last_errors.lock();
+
+        if last.len() == ERRORS_KEPT {
+            last.pop_front();
+        }
+
+        last.push_back(e);
+        self.errors.fetch_add(1, Relaxed);
+    }
+
+    #[cfg_attr(not(feature = "actix_web_04"), allow(dead_code))]
+    pub fn hold(&self) -> Duration {
+        Duration::from_micros(self.hold_us.load(Relaxed))
+    }
+
+    #[cfg(not(feature = "unchecked"))]
+    pub fn record_hold(&self, d: Duration) {
+        let us = d.as_micros() as u64;
+
+        self.hold_us.fetch_add(us, Relaxed);
+        self.max_hold_us.fetch_max(us, Relaxed);
+    }
+
+    #[cfg(not(feature = "unchecked"))]
+    pub fn record_sync_guard_across_await(&self) {
+        self.sync_guards_across_await.fetch_add(1, Relaxed);
+    }
+
+    #[cfg(not(feature = "unchecked"))]
+    pub fn record_wait(&self, d: Duration) {
+        self.wait_us.fetch_add(d.as_micros() as u64, Relaxed);
+    }
+
+    pub fn wait(&self) -> Duration {
+        Duration::from_micros(self.wait_us.load(Relaxed))
+    }
+}
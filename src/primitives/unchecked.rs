@@ -0,0 +1,70 @@
+//! The guards of the `unchecked` feature, tracking nothing.
+//!
+//! Acquiring a lock never fails with a detection error and reports nothing
+//! in the telemetry. The locks are not attributed to their tasks: the deadlock
+//! detection, the lock statistics and the lock dependency graph see no lock,
+//! and the counts of holders and waiters (`is_write_locked`, `reader_count`,
+//! `waiters`...) are not compiled.
+//!
+//! The guards do not read the [clock](crate::Clock) either: the `elapsed`
+//! methods of the guards are not compiled. The `with_deadlock_check`
+//! functions run their future as is and the locks keep no list of holders
+//! or waiters.
+
+use super::LockData;
+use crate::Result;
+use std::{marker::PhantomData, time::Duration};
+
+pub(crate) struct LockAwaitGuard<'a>(PhantomData<&'a LockData>);
+
+impl<'a> LockAwaitGuard<'a> {
+    #[inline]
+    pub fn new(_: &'a LockData, _: &'static str) -> Result<Self> {
+        Ok(Self(PhantomData))
+    }
+}
+
+/// Dropped early by the locks as the tracked guard, to end the wait.
+impl Drop for LockAwaitGuard<'_> {
+    #[inline]
+    fn drop(&mut self) {}
+}
+
+pub(crate) struct LockHeldGuard<'a>(PhantomData<&'a LockData>);
+
+impl<'a> LockHeldGuard<'a> {
+    #[inline]
+    pub fn new(_: LockAwaitGuard<'a>) -> Result<Self> {
+        Ok(Self(PhantomData))
+    }
+
+    #[inline]
+    pub fn new_no_wait(_: &'a LockData, _: &'static str) -> Result<Self> {
+        Ok(Self(PhantomData))
+    }
+
+    #[inline]
+    pub fn attach(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    #[inline]
+    pub fn detach(&mut self) {}
+
+    #[inline]
+    pub fn elapsed(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    #[inline]
+    pub fn set_grouped(&mut self) {}
+
+    #[inline]
+    pub fn set_op(&mut self, _: &'static str) {}
+}
+
+/// Dropped early by the locks as the tracked guard, to end the hold.
+impl Drop for LockHeldGuard<'_> {
+    #[inline]
+    fn drop(&mut self) {}
+}
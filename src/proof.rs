@@ -0,0 +1,135 @@
+use crate::QueueRwLock;
+use std::{
+    fmt::{self, Debug, Formatter},
+    ops::{Deref, DerefMut},
+    ptr,
+};
+
+/// Proves that the caller holds a read, queue or write guard on a
+/// [QueueRwLock], for the helpers to use the value without locking it again
+/// and failing with [RecursiveLock](crate::Error::RecursiveLock).
+pub struct ReadProof<'a, T> {
+    lock: &'a QueueRwLock<T>,
+    value: &'a T,
+}
+
+impl<'a, T> ReadProof<'a, T> {
+    pub(crate) fn new(lock: &'a QueueRwLock<T>, value: &'a T) -> Self {
+        Self { lock, value }
+    }
+
+    /// Returns true if the guard is held on `lock`.
+    pub fn is_for(&self, lock: &QueueRwLock<T>) -> bool {
+        ptr::eq(self.lock, lock)
+    }
+
+    pub fn lock(&self) -> &'a QueueRwLock<T> {
+        self.lock
+    }
+}
+
+impl<T> Clone for ReadProof<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ReadProof<'_, T> {}
+
+impl<T: Debug> Debug for ReadProof<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+impl<T> Deref for ReadProof<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+/// Proves that the caller holds a write guard on a [QueueRwLock], as
+/// [ReadProof] with the mutable access.
+pub struct WriteProof<'a, T> {
+    lock: &'a QueueRwLock<T>,
+    value: &'a mut T,
+}
+
+impl<'a, T> WriteProof<'a, T> {
+    pub(crate) fn new(lock: &'a QueueRwLock<T>, value: &'a mut T) -> Self {
+        Self { lock, value }
+    }
+
+    /// Returns true if the guard is held on `lock`.
+    pub fn is_for(&self, lock: &QueueRwLock<T>) -> bool {
+        ptr::eq(self.lock, lock)
+    }
+
+    pub fn lock(&self) -> &'a QueueRwLock<T> {
+        self.lock
+    }
+
+    /// Reborrows as a [ReadProof], for the helpers only reading.
+    pub fn read(&self) -> ReadProof<'_, T> {
+        ReadProof::new(self.lock, self.value)
+    }
+}
+
+impl<T: Debug> Debug for WriteProof<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+impl<T> Deref for WriteProof<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl<T> DerefMut for WriteProof<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.value
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn helpers_take_proofs() -> crate::Result<()> {
+    fn len(v: ReadProof<'_, Vec<u32>>) -> usize {
+        v.len()
+    }
+
+    fn push(mut v: WriteProof<'_, Vec<u32>>) {
+        let n = len(v.read()) as u32;
+        v.push(n);
+    }
+
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new(vec![], "main_lock");
+            let other = QueueRwLock::new(vec![], "other_lock");
+
+            let mut w = lock.queue().await?.write().await?;
+            push(w.proof_mut());
+            push(w.proof_mut());
+            drop(w);
+
+            let r = lock.read().await?;
+            assert_eq!(len(r.proof()), 2);
+            assert!(r.proof().is_for(&lock));
+            assert!(!r.proof().is_for(&other));
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
@@ -1,36 +1,398 @@
 use crate::{
-    primitives::{LockAwaitGuard, LockData, LockHeldGuard},
-    Error,
+    audit::Auditor,
+    block_on,
+    budget::{BudgetExceeded, BudgetFn, Budgeted},
+    clock::{self, AtomicInstant},
+    new_id,
+    primitives::{task, LockAwaitGuard, LockData, LockHeldGuard, Optimistic, Snapshot},
+    AuditRecord, Error, ReadProof, Thresholds, WriteProof,
 };
+use bytemuck::Pod;
 use std::{
+    collections::VecDeque,
     fmt::{self, Debug, Display, Formatter},
+    future::Future,
+    mem::ManuallyDrop,
     ops::{Deref, DerefMut},
-    time::Duration,
+    panic::Location,
+    ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering::Relaxed},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
-use tokio::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tokio::sync::{watch, Mutex, MutexGuard, Notify, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 pub struct QueueRwLock<T> {
-    lock_data: LockData,
+    audit: Option<Auditor<T>>,
+
+    /// Notified of the writes once [subscribed](QueueRwLock::subscribe) to.
+    changes: once_cell::sync::OnceCell<watch::Sender<u64>>,
+
+    closed: AtomicBool,
+    last_read: AtomicInstant,
+    last_written: AtomicInstant,
+    pub(crate) lock_data: LockData,
     mutex: Mutex<()>,
+    optimistic: Option<Optimistic<T>>,
+    on_budget_exceeded: Option<Arc<BudgetFn>>,
+    poisoned: AtomicBool,
+    poisoning: bool,
     rwlock: RwLock<T>,
+
+    snapshot: Option<Snapshot<T>>,
+
+    /// A copy of the value, set once [sealed](QueueRwLock::seal): the lock is
+    /// closed and no write may be acquired anymore.
+    sealed: once_cell::sync::OnceCell<T>,
+
+    /// Notified when the head of the `waiters` changes.
+    turn: Notify,
+
+    waiters: parking_lot::Mutex<VecDeque<(u64, Priority)>>,
+
+    /// The number of writes, telling whether the value changed in between.
+    writes: AtomicU64,
 }
 
 impl<T> QueueRwLock<T> {
     /// Creates a new instance of an `QueueRwLock<T>` which is unlocked.
     pub fn new(val: T, lock_name: &'static str) -> Self {
+        Self::const_new(val, lock_name)
+    }
+
+    /// Same as [new](Self::new), usable to declare a `static` lock.
+    pub const fn const_new(val: T, lock_name: &'static str) -> Self {
+        Self::from_rwlock(RwLock::const_new(val), lock_name)
+    }
+
+    /// Wraps a tokio `RwLock`, to migrate to this lock without moving
+    /// the value out.
+    pub const fn from_rwlock(rwlock: RwLock<T>, lock_name: &'static str) -> Self {
         Self {
+            audit: None,
+            changes: once_cell::sync::OnceCell::new(),
+            closed: AtomicBool::new(false),
+            last_read: AtomicInstant::none(),
+            last_written: AtomicInstant::none(),
             lock_data: LockData::new(lock_name),
-            mutex: Default::default(),
-            rwlock: RwLock::new(val),
+            mutex: Mutex::const_new(()),
+            on_budget_exceeded: None,
+            optimistic: None,
+            poisoned: AtomicBool::new(false),
+            poisoning: false,
+            rwlock,
+            sealed: once_cell::sync::OnceCell::new(),
+            snapshot: None,
+            turn: Notify::const_new(),
+            waiters: parking_lot::const_mutex(VecDeque::new()),
+            writes: AtomicU64::new(0),
+        }
+    }
+
+    /// Registers a hook invoked each time a write guard is released, with
+    /// the value as it was before the write and the value after.
+    ///
+    /// The returned [AuditRecord] is sent to the telemetry.
+    #[cfg(feature = "telemetry")]
+    pub fn audit<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&T, &T) -> AuditRecord + Send + Sync + 'static,
+        T: Clone,
+    {
+        self.audit = Some(Auditor::new(f, None));
+        self
+    }
+
+    /// Registers a hook invoked each time a write guard is released, with
+    /// the value as it was before the write and the value after.
+    ///
+    /// The returned [AuditRecord] is handed to `sink`, such as an audit log,
+    /// and also sent to the telemetry with the `telemetry` feature.
+    pub fn audit_with<F, S>(mut self, f: F, sink: S) -> Self
+    where
+        F: Fn(&T, &T) -> AuditRecord + Send + Sync + 'static,
+        S: Fn(AuditRecord) + Send + Sync + 'static,
+        T: Clone,
+    {
+        self.audit = Some(Auditor::new(f, Some(Box::new(sink))));
+        self
+    }
+
+    /// Registers a callback invoked when a guard acquired with
+    /// [read_budgeted](Self::read_budgeted) or
+    /// [write_budgeted](QueueRwLockQueueGuard::write_budgeted) is held past
+    /// its budget, while the guard is still held.
+    pub fn on_budget_exceeded<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&BudgetExceeded) + Send + Sync + 'static,
+    {
+        self.on_budget_exceeded = Some(Arc::new(f));
+        self
+    }
+
+    /// Keeps a copy of the value, updated at the end of each write, for
+    /// [read_optimistic](Self::read_optimistic).
+    ///
+    /// The value is [Pod] so it can be copied as plain bytes while a write
+    /// is being published.
+    pub fn optimistic(mut self) -> Self
+    where
+        T: Pod,
+    {
+        self.optimistic = Some(Optimistic::new(self.rwlock.get_mut()));
+        self
+    }
+
+    /// Shares a copy of the value, replaced at the end of each write, for
+    /// [snapshot](Self::snapshot).
+    pub fn snapshots(mut self) -> Self
+    where
+        T: Clone,
+    {
+        self.snapshot = Some(Snapshot::new(self.rwlock.get_mut()));
+        self
+    }
+
+    /// Returns the value as of the last write without taking the read lock
+    /// nor going through the deadlock detection, for the hot read paths.
+    ///
+    /// Returns `None` if the lock has not been built with
+    /// [snapshots](Self::snapshots) or if the value has been changed
+    /// through [get_mut](Self::get_mut) since the last write.
+    pub fn snapshot(&self) -> Option<Arc<T>> {
+        self.snapshot.as_ref()?.get()
+    }
+
+    /// Copies the value without taking the read lock, retrying while a
+    /// write is being published.
+    ///
+    /// Returns `None` if the lock has not been built with
+    /// [optimistic](Self::optimistic) or if the value has been changed
+    /// through [get_mut](Self::get_mut) since the last write.
+    pub fn read_optimistic(&self) -> Option<T>
+    where
+        T: Pod,
+    {
+        self.optimistic.as_ref()?.read()
+    }
+
+    /// Reports the metrics of this lock under `group` instead of its name,
+    /// aggregating them with the other locks of the group.
+    ///
+    /// Each lock remains distinct for the deadlock detection.
+    pub const fn with_group(mut self, group: &'static str) -> Self {
+        self.lock_data.group = group;
+        self
+    }
+
+    /// Allows a task holding a read to read again, the reads being counted
+    /// as one lock held by the deadlock detection.
+    ///
+    /// A read which would wait behind a writer enqueued meanwhile still
+    /// fails with [Error::RecursiveLock], as it would never be granted.
+    pub const fn recursive_reads(mut self) -> Self {
+        self.lock_data.recursive_reads = true;
+        self
+    }
+
+    /// Overrides the durations after which waiting for or holding
+    /// this lock is reported in the telemetry.
+    pub const fn with_thresholds(mut self, thresholds: Thresholds) -> Self {
+        self.lock_data.thresholds = thresholds;
+        self
+    }
+
+    /// Returns true if a read, queue or write guard is held, including the
+    /// guards untracked by the deadlock detection.
+    pub fn is_locked(&self) -> bool {
+        self.mutex.try_lock().is_err() || self.rwlock.try_write().is_err()
+    }
+
+    /// Returns true if a write guard is currently held.
+    #[cfg(not(feature = "unchecked"))]
+    pub fn is_write_locked(&self) -> bool {
+        self.lock_data.holders("write") > 0
+    }
+
+    /// The approximate number of tasks waiting for any access to this lock,
+    /// to surface the backpressure.
+    #[cfg(not(feature = "unchecked"))]
+    pub fn waiters(&self) -> usize {
+        self.lock_data.waiters()
+    }
+
+    /// The approximate number of tasks waiting for the queue, including the
+    /// places taken with [enqueue](Self::enqueue).
+    pub fn queue_len(&self) -> usize {
+        self.waiters.lock().len()
+    }
+
+    /// The approximate number of tasks waiting for a read.
+    #[cfg(not(feature = "unchecked"))]
+    pub fn read_waiters(&self) -> usize {
+        self.lock_data.waiters_with_op("read")
+    }
+
+    /// The approximate number of tasks holding the queue and waiting for the
+    /// readers to leave to write.
+    #[cfg(not(feature = "unchecked"))]
+    pub fn write_waiters(&self) -> usize {
+        self.lock_data.waiters_with_op("write")
+    }
+
+    /// The approximate number of tasks holding a read, including the one
+    /// holding the queue.
+    ///
+    /// With the `telemetry` feature, it is also reported per lock group in
+    /// the `lock_reader_gauge`.
+    #[cfg(not(feature = "unchecked"))]
+    pub fn reader_count(&self) -> usize {
+        self.lock_data.holders("read") + self.lock_data.holders("queue")
+    }
+
+    /// Makes the following attempts to queue or write fail with
+    /// [Error::Closed], while the guards already held drain.
+    ///
+    /// Reading is still allowed.
+    pub fn close(&self) {
+        self.closed.store(true, Relaxed);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Relaxed)
+    }
+
+    /// [Closes](Self::close) the lock for good once the current writes are
+    /// done, returning a reference to the value, from then on also
+    /// available without any locking through [sealed](Self::sealed).
+    ///
+    /// The value is cloned once, while holding the queue.
+    pub async fn seal(&self) -> Result<&T, Error>
+    where
+        T: Clone,
+    {
+        if let Some(value) = self.sealed() {
+            return Ok(value);
+        }
+
+        let queue = match self.queue().await {
+            Ok(queue) => queue,
+            Err(Error::Closed) => return self.sealed().ok_or(Error::Closed),
+            Err(e) => return Err(e),
+        };
+
+        // closed while holding the queue, so the writes check it from now on.
+        self.close();
+
+        Ok(self.sealed.get_or_init(|| queue.read.clone()))
+    }
+
+    pub fn is_sealed(&self) -> bool {
+        self.sealed.get().is_some()
+    }
+
+    /// The value of a [sealed](Self::seal) lock, read without locking.
+    pub fn sealed(&self) -> Option<&T> {
+        self.sealed.get()
+    }
+
+    /// Fails once closed, or once poisoned as any access.
+    fn check_open(&self) -> Result<(), Error> {
+        self.check_poisoned()?;
+
+        match self.is_closed() {
+            true => Err(Error::Closed),
+            false => Ok(()),
+        }
+    }
+
+    fn check_poisoned(&self) -> Result<(), Error> {
+        match self.is_poisoned() {
+            true => Err(Error::Poisoned),
+            false => Ok(()),
         }
     }
 
+    /// Poisons the lock when a write guard is dropped during a panic, as the
+    /// value may be left partially changed: the reads, queues and writes then
+    /// fail with [Error::Poisoned] until [clear_poison](Self::clear_poison).
+    pub fn poisoning(mut self) -> Self {
+        self.poisoning = true;
+        self
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Relaxed)
+    }
+
+    /// Accepts the value as is after a panic, for instance once repaired
+    /// with [get_mut](Self::get_mut) or by a write acquired beforehand.
+    pub fn clear_poison(&self) {
+        self.poisoned.store(false, Relaxed);
+    }
+
+    /// The number of writes released so far, for the readers caching derived
+    /// data to tell whether their cache is stale.
+    ///
+    /// Changes made through [get_mut](Self::get_mut) count as writes.
+    pub fn version(&self) -> u64 {
+        self.writes.load(Relaxed)
+    }
+
+    /// Returns a receiver notified each time a write guard is released, with
+    /// the [version](Self::version), so the caches need not poll the lock.
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.changes
+            .get_or_init(|| watch::channel(self.writes.load(Relaxed)).0)
+            .subscribe()
+    }
+
+    /// When a read or queue guard has last been acquired, as measured by the
+    /// [Clock](crate::Clock).
+    pub fn last_read_at(&self) -> Option<Instant> {
+        self.last_read.load()
+    }
+
+    /// When a write guard has last been released or the value last borrowed
+    /// through [get_mut](Self::get_mut), as measured by the [Clock](crate::Clock).
+    pub fn last_written_at(&self) -> Option<Instant> {
+        self.last_written.load()
+    }
+
+    fn record_read(&self) {
+        self.last_read.fetch_max(clock::now());
+    }
+
     /// Returns a mutable reference to the underlying data.
     ///
     /// Since this call borrows the `RwLock` mutably, no actual locking needs to
     /// take place---the mutable borrow statically guarantees no locks exist.
+    ///
+    /// A [sealed](Self::seal) lock is unsealed and reopened, as its value
+    /// may change.
     #[inline]
     pub fn get_mut(&mut self) -> &mut T {
+        if self.sealed.take().is_some() {
+            *self.closed.get_mut() = false;
+        }
+
+        if let Some(optimistic) = &self.optimistic {
+            optimistic.set_dirty();
+        }
+
+        if let Some(snapshot) = &mut self.snapshot {
+            snapshot.set_dirty();
+        }
+
+        self.last_written.fetch_max(clock::now());
+        *self.writes.get_mut() += 1;
+
+        if let Some(changes) = self.changes.get() {
+            changes.send_replace(*self.writes.get_mut());
+        }
+
         self.rwlock.get_mut()
     }
 
@@ -39,63 +401,357 @@ impl<T> QueueRwLock<T> {
         self.rwlock.into_inner()
     }
 
+    /// Returns the underlying tokio `RwLock` and the name of this lock,
+    /// undoing [from_rwlock](Self::from_rwlock).
+    pub fn into_parts(self) -> (RwLock<T>, &'static str) {
+        (self.rwlock, self.lock_data.name)
+    }
+
+    /// Takes a place in the queue without waiting for it.
+    ///
+    /// The returned [QueueRwLockWaiter] reports its position in line and
+    /// resolves to the queue guard once [granted](QueueRwLockWaiter::granted).
+    ///
+    /// The place is held until the waiter is granted or dropped: a waiter
+    /// reaching the head of the line without being awaited holds up all the
+    /// tasks queued behind it.
+    pub fn enqueue(&self) -> QueueRwLockWaiter<'_, T> {
+        self.enqueue_with_priority(Priority::Normal)
+    }
+
+    /// Same as [enqueue](Self::enqueue), taking a place ahead of the waiters
+    /// of a lower priority, except the one next in line.
+    pub fn enqueue_with_priority(&self, priority: Priority) -> QueueRwLockWaiter<'_, T> {
+        let id = new_id();
+        let mut waiters = self.waiters.lock();
+
+        // the head of the line keeps its turn, being already waiting for the mutex.
+        let index = match waiters.len() {
+            0 => 0,
+            _ => {
+                1 + waiters
+                    .range(1..)
+                    .take_while(|(_, p)| *p >= priority)
+                    .count()
+            }
+        };
+
+        waiters.insert(index, (id, priority));
+
+        QueueRwLockWaiter { id, queue: self }
+    }
+
     /// Enqueue to gain access to the write.
+    ///
+    /// # Cancel safety
+    ///
+    /// Cancel safe: dropping the future gives up the place in the queue and
+    /// leaves the task neither awaiting nor holding the lock.
     pub async fn queue(&self) -> Result<QueueRwLockQueueGuard<'_, T>, Error> {
-        if let Ok(mutex) = self.mutex.try_lock() {
+        self.queue_with_priority(Priority::Normal).await
+    }
+
+    /// Same as [queue](Self::queue), granted before the waiters of a lower
+    /// priority, such as the background maintenance writers.
+    ///
+    /// The waiter next in line keeps its turn.
+    pub async fn queue_with_priority(
+        &self,
+        priority: Priority,
+    ) -> Result<QueueRwLockQueueGuard<'_, T>, Error> {
+        self.check_open()?;
+
+        if let Some(mutex) = self.try_lock_mutex() {
             if let Ok(read) = self.rwlock.try_read() {
+                let active = LockHeldGuard::new_no_wait(&self.lock_data, "queue")?;
+                self.record_read();
+
                 return Ok(QueueRwLockQueueGuard {
-                    active: LockHeldGuard::new_no_wait(&self.lock_data, "queue")?,
+                    active,
                     mutex,
                     queue: self,
+                    queued_for: Duration::ZERO,
                     read,
                 });
             }
         }
 
-        let wait = LockAwaitGuard::new(&self.lock_data, "queue")?;
-        let mutex = self.mutex.lock().await;
+        self.enqueue_with_priority(priority).granted().await
+    }
+
+    /// Same as [queue](Self::queue), giving up the place in the queue with
+    /// [Error::AcquireTimeout] if not granted within `timeout`.
+    pub async fn queue_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<QueueRwLockQueueGuard<'_, T>, Error> {
+        self.acquire_timeout(self.queue(), "queue", timeout).await
+    }
+
+    /// Same as [read](Self::read), returning [Error::AcquireTimeout] if the
+    /// read is not acquired within `timeout`.
+    pub async fn read_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<QueueRwLockReadGuard<'_, T>, Error> {
+        self.acquire_timeout(self.read(), "read", timeout).await
+    }
+
+    /// Same as [queue](Self::queue), giving up the place in the queue with
+    /// [Error::AcquireTimeout] if not granted by `deadline`, such as the one
+    /// of a request.
+    ///
+    /// The deadline follows the tokio time, paused in the tests started with
+    /// `start_paused`, and not the [clock](crate::set_clock) of the telemetry.
+    pub async fn queue_until(
+        &self,
+        deadline: Instant,
+    ) -> Result<QueueRwLockQueueGuard<'_, T>, Error> {
+        self.acquire_until(self.queue(), "queue", deadline.into())
+            .await
+    }
+
+    /// Same as [read](Self::read), returning [Error::AcquireTimeout] if the
+    /// read is not acquired by `deadline`, which follows the tokio time.
+    pub async fn read_until(
+        &self,
+        deadline: Instant,
+    ) -> Result<QueueRwLockReadGuard<'_, T>, Error> {
+        self.acquire_until(self.read(), "read", deadline.into())
+            .await
+    }
+
+    /// Bounds the wait of `f` to `timeout`; the lock wait is recorded as usual
+    /// when `f` is dropped.
+    async fn acquire_timeout<F, G>(&self, f: F, op: &str, timeout: Duration) -> Result<G, Error>
+    where
+        F: Future<Output = Result<G, Error>>,
+    {
+        self.acquire_until(f, op, tokio::time::Instant::now() + timeout)
+            .await
+    }
+
+    /// Same as [acquire_timeout](Self::acquire_timeout) with a deadline.
+    async fn acquire_until<F, G>(
+        &self,
+        f: F,
+        op: &str,
+        deadline: tokio::time::Instant,
+    ) -> Result<G, Error>
+    where
+        F: Future<Output = Result<G, Error>>,
+    {
+        let start = tokio::time::Instant::now();
+
+        match tokio::time::timeout_at(deadline, f).await {
+            Ok(r) => r,
+            Err(_) => Err(Error::acquire_timeout(
+                &self.lock_data,
+                op,
+                deadline.saturating_duration_since(start),
+            )),
+        }
+    }
+
+    /// Locks this `RwLock` with shared read access
+    ///
+    /// # Cancel safety
+    ///
+    /// Cancel safe, as [queue](Self::queue).
+    pub async fn read(&self) -> Result<QueueRwLockReadGuard<'_, T>, Error> {
+        self.check_poisoned()?;
+
+        if let Ok(read) = self.rwlock.try_read() {
+            let active = LockHeldGuard::new_no_wait(&self.lock_data, "read")?;
+            self.record_read();
+
+            return Ok(QueueRwLockReadGuard {
+                active,
+                queue: self,
+                read,
+            });
+        }
+
+        let wait = LockAwaitGuard::new(&self.lock_data, "read")?;
         let read = self.rwlock.read().await;
+        let active = LockHeldGuard::new(wait)?;
 
-        Ok(QueueRwLockQueueGuard {
-            active: LockHeldGuard::new(wait)?,
-            mutex,
+        self.record_read();
+
+        Ok(QueueRwLockReadGuard {
+            active,
             queue: self,
             read,
         })
     }
 
-    /// Locks this `RwLock` with shared read access
-    pub async fn read(&self) -> Result<QueueRwLockReadGuard<'_, T>, Error> {
+    /// Same as [read](Self::read), reporting the read when it is held for
+    /// longer than `budget`.
+    pub async fn read_budgeted(
+        &self,
+        budget: Duration,
+    ) -> Result<Budgeted<QueueRwLockReadGuard<'_, T>>, Error> {
+        let read = self.read().await?;
+        Ok(self.budgeted(read, "read", budget))
+    }
+
+    fn budgeted<G>(&self, guard: G, op: &'static str, budget: Duration) -> Budgeted<G> {
+        let exceeded = BudgetExceeded {
+            budget,
+            name: self.lock_data.name,
+            op,
+        };
+
+        Budgeted::new(guard, exceeded, self.on_budget_exceeded.clone())
+    }
+
+    /// Same as [queue](Self::queue), blocking the current thread, for the
+    /// `spawn_blocking` threads and the synchronous code running inside a
+    /// [blocking::with_deadlock_check](crate::blocking::with_deadlock_check).
+    ///
+    /// Returns [Error::ForbiddenInAsync] inside a `with_deadlock_check` future.
+    #[track_caller]
+    pub fn blocking_queue(&self) -> Result<QueueRwLockQueueGuard<'_, T>, Error> {
+        self.check_blocking("queue")?;
+        self.check_open()?;
+
+        if let Some(guard) = self.try_queue() {
+            return Ok(guard);
+        }
+
+        // waits for its turn in line as `granted`.
+        block_on(self.enqueue().granted())
+    }
+
+    /// Same as [read](Self::read), blocking the current thread as
+    /// [blocking_queue](Self::blocking_queue).
+    #[track_caller]
+    pub fn blocking_read(&self) -> Result<QueueRwLockReadGuard<'_, T>, Error> {
+        self.check_blocking("read")?;
+        self.check_poisoned()?;
+
         if let Ok(read) = self.rwlock.try_read() {
+            let active = LockHeldGuard::new_no_wait(&self.lock_data, "read")?;
+            self.record_read();
+
             return Ok(QueueRwLockReadGuard {
-                active: LockHeldGuard::new_no_wait(&self.lock_data, "read")?,
+                active,
                 queue: self,
                 read,
             });
         }
 
         let wait = LockAwaitGuard::new(&self.lock_data, "read")?;
-        let read = self.rwlock.read().await;
+        let read = self.rwlock.blocking_read();
+        let active = LockHeldGuard::new(wait)?;
+
+        self.record_read();
 
         Ok(QueueRwLockReadGuard {
-            active: LockHeldGuard::new(wait)?,
+            active,
             queue: self,
             read,
         })
     }
 
+    /// Fails instead of letting tokio panic when blocking inside a future.
+    #[track_caller]
+    fn check_blocking(&self, op: &'static str) -> Result<(), Error> {
+        match task::is_async() {
+            true => Err(Error::forbidden_in_async(
+                &self.lock_data,
+                op,
+                Location::caller(),
+            )),
+            false => Ok(()),
+        }
+    }
+
+    /// Attempts to lock with exclusive write access right away, taking the
+    /// queue if free, for the shutdown and recovery paths.
+    ///
+    /// Returns `None` if the lock is contended or closed, or if the write is
+    /// refused by the deadlock detection.
+    pub fn try_write(&self) -> Option<QueueRwLockWriteGuard<'_, T>> {
+        self.check_open().ok()?;
+
+        // the queue is held while taking the write, as in `write`.
+        let mutex = self.try_lock_mutex()?;
+        let write = self.rwlock.try_write().ok()?;
+        let active = LockHeldGuard::new_no_wait(&self.lock_data, "write").ok()?;
+
+        drop(mutex);
+
+        Some(QueueRwLockWriteGuard {
+            active,
+            audit: self.audit.as_ref().map(|a| a.snapshot(&write)),
+            queue: self,
+            restored: false,
+            write,
+        })
+    }
+
+    /// Holds the queue while running `f`, which can't await and so can't
+    /// keep the queue across a long await by accident.
+    ///
+    /// The time the queue is held is recorded as for any queue guard.
+    pub async fn with_queue<F, R>(&self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        Ok(f(&*self.queue().await?))
+    }
+
+    /// Same as [with_queue](Self::with_queue) with the write access.
+    pub async fn with_write<F, R>(&self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        Ok(f(&mut *self.queue().await?.write().await?))
+    }
+
+    /// Runs `prepare` under the queue, where the readers still have access,
+    /// then `apply` with its result under the write, keeping the heavy work
+    /// out of the write.
+    pub async fn queue_scoped<P, F, G, R>(&self, prepare: F, apply: G) -> Result<R, Error>
+    where
+        F: FnOnce(&T) -> P,
+        G: FnOnce(&mut T, P) -> R,
+    {
+        let queue = self.queue().await?;
+        let prepared = prepare(&queue);
+
+        Ok(apply(&mut *queue.write().await?, prepared))
+    }
+
+    /// Locks the mutex of the queue right away, unless a task is waiting in
+    /// line: the newcomers never pass the waiters, whatever their priority.
+    fn try_lock_mutex(&self) -> Option<MutexGuard<'_, ()>> {
+        let waiters = self.waiters.lock();
+
+        match waiters.is_empty() {
+            true => self.mutex.try_lock().ok(),
+            false => None,
+        }
+    }
+
     /// Attempts to acquire the queue, and returns `None` if any
     /// somewhere else is in the queue.
     pub fn try_queue(&self) -> Option<QueueRwLockQueueGuard<'_, T>> {
+        self.check_open().ok()?;
+
         // mutex must be locked first, before the read.
-        let mutex = self.mutex.try_lock().ok()?;
+        let mutex = self.try_lock_mutex()?;
         let read = self.rwlock.try_read().ok()?;
         let active = LockHeldGuard::new_no_wait(&self.lock_data, "queue").ok()?;
 
+        self.record_read();
+
         Some(QueueRwLockQueueGuard {
             active,
             mutex,
             queue: self,
+            queued_for: Duration::ZERO,
             read,
         })
     }
@@ -107,6 +763,94 @@ impl<T: Default> Default for QueueRwLock<T> {
     }
 }
 
+/// The precedence of a writer in the queue of a [QueueRwLock].
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+/// A place in the queue of a [QueueRwLock], obtained with
+/// [enqueue](QueueRwLock::enqueue).
+///
+/// Dropping it before being granted gives up the place.
+pub struct QueueRwLockWaiter<'a, T> {
+    id: u64,
+    queue: &'a QueueRwLock<T>,
+}
+
+impl<'a, T> QueueRwLockWaiter<'a, T> {
+    /// The number of tasks enqueued ahead of this one; zero when next in line.
+    pub fn position(&self) -> usize {
+        let waiters = self.queue.waiters.lock();
+        waiters
+            .iter()
+            .position(|(id, _)| *id == self.id)
+            .unwrap_or(0)
+    }
+
+    /// Waits for the turn of this place in the queue.
+    ///
+    /// # Cancel safety
+    ///
+    /// Cancel safe, as [queue](QueueRwLock::queue).
+    pub async fn granted(self) -> Result<QueueRwLockQueueGuard<'a, T>, Error> {
+        let queue = self.queue;
+
+        queue.check_open()?;
+
+        let wait = LockAwaitGuard::new(&queue.lock_data, "queue")?;
+        let instant = clock::now();
+
+        // only the head of the line waits for the mutex, which is first in first out.
+        loop {
+            let turn = queue.turn.notified();
+
+            if self.position() == 0 {
+                break;
+            }
+
+            turn.await;
+        }
+
+        let mutex = queue.mutex.lock().await;
+
+        drop(self);
+
+        // closed while waiting.
+        queue.check_open()?;
+
+        let read = queue.rwlock.read().await;
+        let active = LockHeldGuard::new(wait)?;
+
+        queue.record_read();
+
+        Ok(QueueRwLockQueueGuard {
+            active,
+            mutex,
+            queue,
+            queued_for: clock::elapsed(instant),
+            read,
+        })
+    }
+}
+
+impl<T> Drop for QueueRwLockWaiter<'_, T> {
+    fn drop(&mut self) {
+        let mut waiters = self.queue.waiters.lock();
+
+        if let Some(index) = waiters.iter().position(|(id, _)| *id == self.id) {
+            waiters.remove(index);
+
+            if index == 0 {
+                self.queue.turn.notify_waiters();
+            }
+        }
+    }
+}
+
 pub struct QueueRwLockReadGuard<'a, T> {
     active: LockHeldGuard<'a>,
     queue: &'a QueueRwLock<T>,
@@ -114,26 +858,93 @@ pub struct QueueRwLockReadGuard<'a, T> {
 }
 
 impl<'a, T> QueueRwLockReadGuard<'a, T> {
+    #[cfg(not(feature = "unchecked"))]
     pub fn elapsed(&self) -> Duration {
         self.active.elapsed()
     }
 
+    /// The [version](QueueRwLock::version) of the value read.
+    pub fn version(&self) -> u64 {
+        self.queue.version()
+    }
+
+    /// Proves to the helpers that the read is held.
+    pub fn proof(&self) -> ReadProof<'_, T> {
+        ReadProof::new(self.queue, &self.read)
+    }
+
+    pub(crate) fn set_grouped(&mut self) {
+        self.active.set_grouped();
+    }
+
     pub async fn queue(self) -> Result<QueueRwLockQueueGuard<'a, T>, Error> {
         drop(self.active);
         drop(self.read);
 
         self.queue.queue().await
     }
-}
-
-impl<T> Debug for QueueRwLockReadGuard<'_, T>
-where
-    T: Debug,
-{
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        self.read.deref().fmt(f)
-    }
-}
+
+    /// Attempts to acquire the queue while keeping this read,
+    /// returning the read guard back if the queue is busy.
+    ///
+    /// Unlike [queue](Self::queue), the value cannot change in between.
+    pub fn try_queue(self) -> Result<QueueRwLockQueueGuard<'a, T>, Self> {
+        if self.queue.is_closed() {
+            return Err(self);
+        }
+
+        let Some(mutex) = self.queue.try_lock_mutex() else {
+            return Err(self);
+        };
+
+        let mut active = self.active;
+        active.set_op("queue");
+
+        Ok(QueueRwLockQueueGuard {
+            active,
+            mutex,
+            queue: self.queue,
+            queued_for: Duration::ZERO,
+            read: self.read,
+        })
+    }
+
+    /// Upgrades to the queue, keeping this read when the queue is free so the
+    /// value cannot change in between.
+    ///
+    /// Otherwise the read is released while waiting for the queue, as the
+    /// holder of the queue may need it to write, and the returned flag tells
+    /// whether the value has been written meanwhile and needs revalidation.
+    pub async fn upgrade(self) -> Result<(QueueRwLockQueueGuard<'a, T>, bool), Error> {
+        let queue = self.queue;
+        let writes = queue.writes.load(Relaxed);
+
+        match self.try_queue() {
+            Ok(guard) => Ok((guard, false)),
+            Err(read) => {
+                let guard = read.queue().await?;
+                Ok((guard, queue.writes.load(Relaxed) != writes))
+            }
+        }
+    }
+
+    /// Returns the underlying `tokio` read guard.
+    ///
+    /// The read is untracked from here on: the deadlock detection no longer
+    /// sees it and the held duration stops being measured.
+    pub fn into_tokio_guard(self) -> RwLockReadGuard<'a, T> {
+        self.read
+    }
+}
+
+impl<T> Debug for QueueRwLockReadGuard<'_, T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.read.deref().fmt(f)
+    }
+}
 
 impl<T> Deref for QueueRwLockReadGuard<'_, T> {
     type Target = T;
@@ -162,14 +973,44 @@ pub struct QueueRwLockQueueGuard<'a, T> {
     active: LockHeldGuard<'a>,
     mutex: MutexGuard<'a, ()>,
     queue: &'a QueueRwLock<T>,
+    queued_for: Duration,
     read: RwLockReadGuard<'a, T>,
 }
 
 impl<'a, T> QueueRwLockQueueGuard<'a, T> {
+    /// The time elapsed since the queue has been acquired.
+    #[cfg(not(feature = "unchecked"))]
     pub fn elapsed(&self) -> Duration {
         self.active.elapsed()
     }
 
+    /// The [version](QueueRwLock::version) of the value read.
+    pub fn version(&self) -> u64 {
+        self.queue.version()
+    }
+
+    /// Proves to the helpers that the queue, and so a read, is held.
+    pub fn proof(&self) -> ReadProof<'_, T> {
+        ReadProof::new(self.queue, &self.read)
+    }
+
+    /// The time spent waiting behind other writers before acquiring the queue.
+    pub fn queued_for(&self) -> Duration {
+        self.queued_for
+    }
+
+    pub(crate) fn set_grouped(&mut self) {
+        self.active.set_grouped();
+    }
+
+    /// Returns the underlying `tokio` guards: the queue mutex and the read.
+    ///
+    /// The queue is untracked from here on: the deadlock detection no longer
+    /// sees it and the held duration stops being measured.
+    pub fn into_tokio_guards(self) -> (MutexGuard<'a, ()>, RwLockReadGuard<'a, T>) {
+        (self.mutex, self.read)
+    }
+
     /// Locks this `RwLock` with exclusive write access, blocking the current
     /// thread until it can be acquired.
     ///
@@ -177,34 +1018,241 @@ impl<'a, T> QueueRwLockQueueGuard<'a, T> {
     /// currently have access to the lock.
     ///
     /// This will also release the queue so another potential writer will get access.
+    ///
+    /// With the `telemetry` feature, the time from the acquisition of the queue
+    /// is recorded in the `queue_to_write_latency` histogram, in milliseconds.
+    ///
+    /// # Cancel safety
+    ///
+    /// Dropping the future releases the queue, leaving the task neither
+    /// awaiting nor holding the lock.
     pub async fn write(self) -> Result<QueueRwLockWriteGuard<'a, T>, Error> {
+        let queue = self.queue;
+
+        queue.check_open()?;
+
+        #[cfg(feature = "telemetry")]
+        let queued_since = clock::now() - self.active.elapsed();
+
+        let audit = queue.audit.as_ref().map(|a| a.snapshot(&self.read));
+
         // the read lock must be dropped before trying to acquire write lock.
         drop(self.active);
         drop(self.read);
 
+        if let Ok(write) = queue.rwlock.try_write() {
+            // emphasis here that the mutex must be dropped after the write.
+            drop(self.mutex);
+
+            let guard = QueueRwLockWriteGuard {
+                active: LockHeldGuard::new_no_wait(&queue.lock_data, "write")?,
+                audit,
+                queue,
+                restored: false,
+                write,
+            };
+
+            #[cfg(feature = "telemetry")]
+            record_queue_to_write(&queue.lock_data, queued_since);
+
+            return Ok(guard);
+        }
+
+        let wait = LockAwaitGuard::new(&queue.lock_data, "write")?;
+        let write = queue.rwlock.write().await;
+
+        // emphasis here that the mutex must be dropped after the write.
+        drop(self.mutex);
+
+        let guard = QueueRwLockWriteGuard {
+            active: LockHeldGuard::new(wait)?,
+            audit,
+            queue,
+            restored: false,
+            write,
+        };
+
+        #[cfg(feature = "telemetry")]
+        record_queue_to_write(&queue.lock_data, queued_since);
+
+        Ok(guard)
+    }
+
+    /// Same as [write](Self::write), blocking the current thread as
+    /// [blocking_queue](QueueRwLock::blocking_queue).
+    #[track_caller]
+    pub fn blocking_write(self) -> Result<QueueRwLockWriteGuard<'a, T>, Error> {
         let queue = self.queue;
 
+        queue.check_blocking("write")?;
+        queue.check_open()?;
+
+        #[cfg(feature = "telemetry")]
+        let queued_since = clock::now() - self.active.elapsed();
+
+        let audit = queue.audit.as_ref().map(|a| a.snapshot(&self.read));
+
+        // the read lock must be dropped before trying to acquire write lock.
+        drop(self.active);
+        drop(self.read);
+
         if let Ok(write) = queue.rwlock.try_write() {
             // emphasis here that the mutex must be dropped after the write.
             drop(self.mutex);
 
-            return Ok(QueueRwLockWriteGuard {
+            let guard = QueueRwLockWriteGuard {
                 active: LockHeldGuard::new_no_wait(&queue.lock_data, "write")?,
+                audit,
                 queue,
+                restored: false,
                 write,
-            });
+            };
+
+            #[cfg(feature = "telemetry")]
+            record_queue_to_write(&queue.lock_data, queued_since);
+
+            return Ok(guard);
         }
 
         let wait = LockAwaitGuard::new(&queue.lock_data, "write")?;
-        let write = queue.rwlock.write().await;
+        let write = queue.rwlock.blocking_write();
 
         // emphasis here that the mutex must be dropped after the write.
         drop(self.mutex);
 
-        Ok(QueueRwLockWriteGuard {
+        let guard = QueueRwLockWriteGuard {
             active: LockHeldGuard::new(wait)?,
+            audit,
             queue,
+            restored: false,
             write,
+        };
+
+        #[cfg(feature = "telemetry")]
+        record_queue_to_write(&queue.lock_data, queued_since);
+
+        Ok(guard)
+    }
+
+    /// Releases the queue to the next writer, keeping the read so the value
+    /// cannot change in between.
+    pub fn into_read(self) -> QueueRwLockReadGuard<'a, T> {
+        let mut active = self.active;
+
+        drop(self.mutex);
+        active.set_op("read");
+
+        QueueRwLockReadGuard {
+            active,
+            queue: self.queue,
+            read: self.read,
+        }
+    }
+
+    /// Same as [write](Self::write) when `predicate` holds on the value, such
+    /// as when a prepared change is not empty, otherwise releases the queue
+    /// right away and returns `None`, counting the skip in the telemetry.
+    pub async fn write_if<F>(
+        self,
+        predicate: F,
+    ) -> Result<Option<QueueRwLockWriteGuard<'a, T>>, Error>
+    where
+        F: FnOnce(&T) -> bool,
+    {
+        if predicate(&self.read) {
+            return self.write().await.map(Some);
+        }
+
+        #[cfg(feature = "telemetry")]
+        metrics::counter!("lock_write_skipped_counter", "name" => self.queue.lock_data.group)
+            .increment(1);
+
+        Ok(None)
+    }
+
+    /// Same as [write](Self::write), returning [Error::AcquireTimeout] if the
+    /// readers are not gone within `timeout`; the queue is then released.
+    pub async fn write_timeout(
+        self,
+        timeout: Duration,
+    ) -> Result<QueueRwLockWriteGuard<'a, T>, Error> {
+        let queue = self.queue;
+        queue.acquire_timeout(self.write(), "write", timeout).await
+    }
+
+    /// Same as [write](Self::write), returning [Error::AcquireTimeout] if the
+    /// readers are not gone by `deadline`, which follows the tokio time; the
+    /// queue is then released.
+    pub async fn write_until(
+        self,
+        deadline: Instant,
+    ) -> Result<QueueRwLockWriteGuard<'a, T>, Error> {
+        let queue = self.queue;
+        queue
+            .acquire_until(self.write(), "write", deadline.into())
+            .await
+    }
+
+    /// Same as [write](Self::write), reporting the write when it is held
+    /// for longer than `budget`.
+    pub async fn write_budgeted(
+        self,
+        budget: Duration,
+    ) -> Result<Budgeted<QueueRwLockWriteGuard<'a, T>>, Error> {
+        let queue = self.queue;
+        let write = self.write().await?;
+
+        Ok(queue.budgeted(write, "write", budget))
+    }
+
+    /// Locks this `RwLock` with exclusive write access and runs `f` on the
+    /// value, holding the write until the `deadline` at most.
+    ///
+    /// Past the deadline, which follows the tokio time, `f` is dropped where
+    /// it awaits, the write is released and [Error::LeaseExpired] is returned.
+    /// The value may be left partially changed: the lock is then poisoned if
+    /// created with [poisoning](QueueRwLock::poisoning).
+    pub async fn write_leased<F, R>(self, deadline: Instant, f: F) -> Result<R, Error>
+    where
+        F: AsyncFnOnce(&mut T) -> R,
+    {
+        let queue = self.queue;
+        let mut write = self.write().await?;
+
+        match tokio::time::timeout_at(deadline.into(), f(&mut write)).await {
+            Ok(r) => Ok(r),
+            Err(_) => {
+                if queue.poisoning {
+                    queue.poisoned.store(true, Relaxed);
+                }
+
+                #[cfg(feature = "telemetry")]
+                {
+                    metrics::counter!("lock_lease_expired_counter", "name" => queue.lock_data.group)
+                        .increment(1);
+
+                    tracing::warn!(name = queue.lock_data.name, "Write lease expired");
+                }
+
+                Err(Error::LeaseExpired)
+            }
+        }
+    }
+}
+
+impl<'a, T: Clone> QueueRwLockQueueGuard<'a, T> {
+    /// Locks this `RwLock` with exclusive write access, taking a snapshot of
+    /// the value beforehand so the changes can be rolled back.
+    ///
+    /// The snapshot is cloned while holding the queue, before the write
+    /// access is obtained. If the returned guard is dropped during a panic,
+    /// the snapshot is restored automatically.
+    pub async fn write_transactional(self) -> Result<QueueRwLockTransactionGuard<'a, T>, Error> {
+        let snapshot = self.read.deref().clone();
+
+        Ok(QueueRwLockTransactionGuard {
+            snapshot: Some(snapshot),
+            write: self.write().await?,
         })
     }
 }
@@ -236,27 +1284,104 @@ where
     }
 }
 
+/// Records the time from the acquisition of the queue to the one of the write.
+#[cfg(feature = "telemetry")]
+fn record_queue_to_write(lock_data: &LockData, queued_since: Instant) {
+    metrics::histogram!("queue_to_write_latency", "name" => lock_data.group)
+        .record(clock::elapsed(queued_since).as_secs_f64() * 1000.0);
+}
+
 pub struct QueueRwLockWriteGuard<'a, T> {
     active: LockHeldGuard<'a>,
+    audit: Option<T>,
     queue: &'a QueueRwLock<T>,
+
+    /// Set once a transaction restored its snapshot during a panic, leaving
+    /// a consistent value which does not poison the lock.
+    restored: bool,
     write: RwLockWriteGuard<'a, T>,
 }
 
 impl<'a, T> QueueRwLockWriteGuard<'a, T> {
+    /// Attributes the write to the current task, after a [detach](Self::detach).
+    pub(crate) fn attach(&mut self) -> Result<(), Error> {
+        self.active.attach()
+    }
+
+    /// Stops attributing the write to the current task.
+    pub(crate) fn detach(&mut self) {
+        self.active.detach();
+    }
+
+    /// Proves to the helpers that the write is held.
+    pub fn proof(&self) -> ReadProof<'_, T> {
+        ReadProof::new(self.queue, &self.write)
+    }
+
+    /// Proves to the helpers that the write is held, giving them the
+    /// mutable access.
+    pub fn proof_mut(&mut self) -> WriteProof<'_, T> {
+        WriteProof::new(self.queue, &mut self.write)
+    }
+
+    pub(crate) fn set_grouped(&mut self) {
+        self.active.set_grouped();
+    }
+
+    /// Returns the underlying `tokio` write guard.
+    ///
+    /// The write is recorded as done first: the version, the watchers, the
+    /// audit and the poisoning see it as if the guard was dropped here. The
+    /// changes made through the returned guard are not published, and the
+    /// write is untracked from here on.
+    pub fn into_tokio_guard(mut self) -> RwLockWriteGuard<'a, T> {
+        self.release();
+
+        if let Some(optimistic) = &self.queue.optimistic {
+            optimistic.set_dirty();
+        }
+
+        let mut s = ManuallyDrop::new(self);
+
+        // SAFETY: each field is moved out once from a guard which is never dropped.
+        let (active, write) = unsafe { (ptr::read(&s.active), ptr::read(&s.write)) };
+
+        drop(s.audit.take());
+        drop(active);
+        write
+    }
+
     pub async fn read(self) -> Result<QueueRwLockReadGuard<'a, T>, Error> {
+        let queue = self.queue;
+
         // drop the write lock before trying to acquire the read.
-        drop(self.write);
-        drop(self.active);
+        drop(self);
 
-        self.queue.read().await
+        queue.read().await
     }
 
     pub async fn queue(self) -> Result<QueueRwLockQueueGuard<'a, T>, Error> {
+        let queue = self.queue;
+
         // drop the write lock before trying to acquire the queue.
-        drop(self.write);
-        drop(self.active);
+        drop(self);
 
-        self.queue.queue().await
+        queue.queue().await
+    }
+}
+
+impl<'a, T: Clone> QueueRwLockWriteGuard<'a, T> {
+    /// Turns this write into a transaction, taking a snapshot of the value
+    /// restored on [rollback](QueueRwLockTransactionGuard::rollback) or
+    /// during a panic.
+    ///
+    /// Unlike [write_transactional](QueueRwLockQueueGuard::write_transactional),
+    /// the snapshot is cloned while holding the write.
+    pub fn transaction(self) -> QueueRwLockTransactionGuard<'a, T> {
+        QueueRwLockTransactionGuard {
+            snapshot: Some(self.write.deref().clone()),
+            write: self,
+        }
     }
 }
 
@@ -304,7 +1429,112 @@ where
     }
 }
 
-#[cfg(test)]
+impl<T> QueueRwLockWriteGuard<'_, T> {
+    /// Records the end of the write.
+    fn release(&mut self) {
+        if let (Some(audit), Some(old)) = (&self.queue.audit, self.audit.take()) {
+            audit.record(&self.queue.lock_data, &old, &self.write);
+        }
+
+        if let Some(optimistic) = &self.queue.optimistic {
+            optimistic.publish(&self.write);
+        }
+
+        if let Some(snapshot) = &self.queue.snapshot {
+            snapshot.publish(&self.write);
+        }
+
+        self.queue.last_written.fetch_max(clock::now());
+        if self.queue.poisoning && !self.restored && std::thread::panicking() {
+            self.queue.poisoned.store(true, Relaxed);
+        }
+
+        let writes = self.queue.writes.fetch_add(1, Relaxed) + 1;
+
+        if let Some(changes) = self.queue.changes.get() {
+            changes.send_replace(writes);
+        }
+    }
+}
+
+impl<T> Drop for QueueRwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// A write guard holding a snapshot of the value taken before the write.
+///
+/// Call [commit](Self::commit) to keep the changes or [rollback](Self::rollback)
+/// to restore the snapshot. Dropping the guard during a panic restores the
+/// snapshot, without poisoning the lock; dropping it otherwise keeps the changes.
+pub struct QueueRwLockTransactionGuard<'a, T> {
+    snapshot: Option<T>,
+    write: QueueRwLockWriteGuard<'a, T>,
+}
+
+impl<T> QueueRwLockTransactionGuard<'_, T> {
+    /// Keeps the changes and releases the write lock.
+    pub fn commit(mut self) {
+        self.snapshot = None;
+    }
+
+    /// Restores the snapshot and releases the write lock.
+    pub fn rollback(mut self) {
+        self.restore();
+    }
+
+    fn restore(&mut self) {
+        if let Some(snapshot) = self.snapshot.take() {
+            *self.write = snapshot;
+        }
+    }
+}
+
+impl<T> Debug for QueueRwLockTransactionGuard<'_, T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.write.deref().fmt(f)
+    }
+}
+
+impl<T> Deref for QueueRwLockTransactionGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.write
+    }
+}
+
+impl<T> DerefMut for QueueRwLockTransactionGuard<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.write
+    }
+}
+
+impl<T> Display for QueueRwLockTransactionGuard<'_, T>
+where
+    T: Display,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.write.deref().fmt(f)
+    }
+}
+
+impl<T> Drop for QueueRwLockTransactionGuard<'_, T> {
+    fn drop(&mut self) {
+        if std::thread::panicking() && self.snapshot.is_some() {
+            self.restore();
+            self.write.restored = true;
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
 #[tokio::test]
 async fn check_deadlock() -> Result<(), Error> {
     use crate::primitives::locks_held::has_lock_held;
@@ -350,7 +1580,7 @@ async fn check_deadlock() -> Result<(), Error> {
     .await
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(feature = "unchecked")))]
 #[tokio::test]
 async fn should_error_if_run_without_deadlock_check() {
     use crate::primitives::locks_held::has_lock_held;
@@ -369,3 +1599,1159 @@ async fn should_error_if_run_without_deadlock_check() {
 
     assert!(!has_lock_held());
 }
+
+#[cfg(test)]
+#[tokio::test]
+async fn write_transactional_rollback() -> Result<(), Error> {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new(vec![1], "main_lock");
+
+            let mut w = lock.queue().await?.write_transactional().await?;
+            w.push(2);
+            w.rollback();
+            assert_eq!(*lock.read().await?, vec![1]);
+
+            let mut w = lock.queue().await?.write_transactional().await?;
+            w.push(3);
+            w.commit();
+            assert_eq!(*lock.read().await?, vec![1, 3]);
+
+            let w = lock.queue().await?.write_transactional().await?;
+
+            let r = catch_unwind(AssertUnwindSafe(move || {
+                let mut w = w;
+                w.push(4);
+                panic!("failed halfway");
+            }));
+
+            assert!(r.is_err());
+            assert_eq!(*lock.read().await?, vec![1, 3]);
+
+            let mut w = lock.queue().await?.write().await?;
+            w.push(5);
+
+            let mut w = w.transaction();
+            w.push(6);
+            w.rollback();
+            assert_eq!(*lock.read().await?, vec![1, 3, 5]);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn audit_write() -> Result<(), Error> {
+    use std::sync::{Arc, Mutex};
+
+    let records = Arc::new(Mutex::new(Vec::new()));
+    let records2 = Arc::clone(&records);
+
+    let lock = QueueRwLock::new(1, "main_lock").audit_with(
+        |old, new| AuditRecord::new(format!("{old} -> {new}")),
+        move |record| records2.lock().unwrap().push(record),
+    );
+
+    crate::with_deadlock_check(
+        async move {
+            let mut w = lock.queue().await?.write().await?;
+            *w = 2;
+            drop(w);
+
+            let mut w = lock.queue().await?.write().await?;
+            *w = 3;
+            let _r = w.read().await?;
+
+            Ok::<_, Error>(())
+        },
+        "lock_test".into(),
+    )
+    .await?;
+
+    assert_eq!(
+        *records.lock().unwrap(),
+        vec![AuditRecord::from("1 -> 2"), AuditRecord::from("2 -> 3")]
+    );
+
+    Ok(())
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn held_locks_names() {
+    use crate::test_util::{assert_no_locks_held, held_locks, run_with_deadlock_check};
+
+    run_with_deadlock_check(
+        async move {
+            let a = QueueRwLock::new((), "lock_a");
+            let b = QueueRwLock::new((), "lock_b");
+
+            let r = a.read().await?;
+            let q = b.queue().await?;
+
+            assert_eq!(held_locks(), vec!["lock_a", "lock_b"]);
+
+            drop(r);
+            drop(q);
+
+            assert_no_locks_held();
+
+            Ok::<_, Error>(())
+        },
+        "lock_test",
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test(start_paused = true)]
+async fn write_leased_expires() -> Result<(), Error> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new(1, "main_lock").poisoning();
+            let deadline = Instant::now() + Duration::from_secs(60);
+
+            let r = lock
+                .queue()
+                .await?
+                .write_leased(deadline, async |v| *v = 2)
+                .await;
+
+            assert_eq!(r, Ok(()));
+            assert_eq!(*lock.read().await?, 2);
+
+            let r = lock
+                .queue()
+                .await?
+                .write_leased(deadline, async |v| {
+                    *v = 3;
+                    tokio::time::sleep(Duration::from_secs(120)).await;
+                    *v = 4;
+                })
+                .await;
+
+            // released at the deadline, half changed.
+            assert_eq!(r, Err(Error::LeaseExpired));
+            assert!(!lock.is_locked());
+            assert!(lock.is_poisoned());
+
+            lock.clear_poison();
+            assert_eq!(*lock.read().await?, 3);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn read_try_queue() -> Result<(), Error> {
+    use crate::test_util::held_locks;
+
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new((), "main_lock");
+            let q = lock.try_queue().unwrap();
+            let r = lock.read().await?;
+
+            // queue is busy, the read is given back.
+            let r = r.try_queue().unwrap_err();
+            drop(q);
+
+            let acquired = task::current()?.stats.lock_stats().acquired;
+            let q = r.try_queue().unwrap();
+            assert_eq!(held_locks(), vec!["main_lock"]);
+
+            // The read is turned into the queue in place, not acquired again.
+            let held = task::current()?.held();
+            assert_eq!(held.len(), 1);
+            assert_eq!(held[0].2, "queue");
+            assert_eq!(task::current()?.stats.lock_stats().acquired, acquired);
+
+            drop(q);
+            assert!(held_locks().is_empty());
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn group_locks_are_distinct() -> Result<(), Error> {
+    crate::with_deadlock_check(
+        async move {
+            let a = QueueRwLock::new((), "tenant-a").with_group("tenant-state");
+            let b = QueueRwLock::new((), "tenant-b").with_group("tenant-state");
+
+            let _a = a.queue().await?;
+            let _b = b.queue().await?;
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn enqueue_position() -> Result<(), Error> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new(0, "main_lock");
+            let first = lock.enqueue();
+            let second = lock.enqueue();
+            let third = lock.enqueue();
+
+            assert_eq!(first.position(), 0);
+            assert_eq!(third.position(), 2);
+
+            drop(second);
+            assert_eq!(third.position(), 1);
+
+            drop(first.granted().await?);
+            assert_eq!(third.position(), 0);
+
+            *third.granted().await?.write().await? = 1;
+            assert_eq!(*lock.read().await?, 1);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn occupancy() -> Result<(), Error> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new((), "main_lock");
+            let q = lock.queue().await?;
+
+            assert_eq!(lock.reader_count(), 1);
+            assert!(!lock.is_write_locked());
+
+            let w = q.write().await?;
+
+            assert_eq!(lock.reader_count(), 0);
+            assert!(lock.is_write_locked());
+
+            drop(w);
+            assert!(!lock.is_write_locked());
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn read_optimistic() -> Result<(), Error> {
+    crate::with_deadlock_check(
+        async move {
+            let mut lock = QueueRwLock::new([1, 2], "main_lock").optimistic();
+
+            assert_eq!(lock.read_optimistic(), Some([1, 2]));
+
+            *lock.queue().await?.write().await? = [3, 4];
+            assert_eq!(lock.read_optimistic(), Some([3, 4]));
+
+            lock.get_mut()[0] = 5;
+            assert_eq!(lock.read_optimistic(), None);
+
+            drop(lock.queue().await?.write().await?);
+            assert_eq!(lock.read_optimistic(), Some([5, 4]));
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test(start_paused = true)]
+async fn budget_exceeded_while_held() -> Result<(), Error> {
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    let exceeded = Arc::new(AtomicUsize::new(0));
+    let count = Arc::clone(&exceeded);
+    let lock = QueueRwLock::new((), "main_lock").on_budget_exceeded(move |e| {
+        assert_eq!(e.op, "write");
+        count.fetch_add(1, Relaxed);
+    });
+
+    crate::with_deadlock_check(
+        async move {
+            let budget = Duration::from_millis(10);
+            let w = lock.queue().await?.write_budgeted(budget).await?;
+
+            tokio::time::sleep(budget * 2).await;
+            assert_eq!(exceeded.load(Relaxed), 1);
+            drop(w);
+
+            let w = lock.queue().await?.write_budgeted(budget).await?;
+            drop(w);
+
+            tokio::time::sleep(budget * 2).await;
+            assert_eq!(exceeded.load(Relaxed), 1);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn last_read_and_written_at() -> Result<(), Error> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new(0, "main_lock");
+
+            assert_eq!(lock.last_read_at(), None);
+            assert_eq!(lock.last_written_at(), None);
+
+            let q = lock.queue().await?;
+            let read_at = lock.last_read_at().unwrap();
+
+            *q.write().await? = 1;
+
+            assert_eq!(lock.last_read_at(), Some(read_at));
+            assert!(lock.last_written_at().unwrap() >= read_at);
+
+            drop(lock.read().await?);
+            assert!(lock.last_read_at().unwrap() >= lock.last_written_at().unwrap());
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(all(test, feature = "unchecked"))]
+#[tokio::test]
+async fn unchecked_outside_deadlock_check() -> Result<(), Error> {
+    let lock = QueueRwLock::new(0, "main_lock");
+
+    *lock.queue().await?.write().await? = 1;
+    assert_eq!(*lock.read().await?, 1);
+    assert!(!lock.is_locked());
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn close_rejects_writes() -> Result<(), Error> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new(0, "main_lock");
+            let q = lock.queue().await?;
+
+            lock.close();
+
+            assert!(lock.is_closed());
+            assert_eq!(q.write().await.err(), Some(Error::Closed));
+            assert_eq!(lock.queue().await.err(), Some(Error::Closed));
+            assert!(lock.try_queue().is_none());
+            assert_eq!(*lock.read().await?, 0);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn seal_then_read_without_lock() -> Result<(), Error> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new(vec![1], "main_lock");
+
+            assert!(lock.sealed().is_none());
+
+            lock.queue().await?.write().await?.push(2);
+
+            assert_eq!(lock.seal().await?, &[1, 2]);
+            assert_eq!(lock.sealed(), Some(&vec![1, 2]));
+            assert_eq!(lock.seal().await?, &[1, 2]);
+            assert_eq!(lock.queue().await.err(), Some(Error::Closed));
+            assert_eq!(*lock.read().await?, [1, 2]);
+
+            // no read is left behind.
+            assert!(!lock.is_locked());
+
+            let lock = Box::new(lock);
+            assert_eq!(lock.sealed(), Some(&vec![1, 2]));
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn sealed_get_mut_unseals() -> Result<(), Error> {
+    let mut lock = QueueRwLock::new(0, "main_lock");
+
+    crate::with_deadlock_check(lock.seal(), "lock_test".into()).await?;
+
+    *lock.get_mut() = 1;
+
+    assert!(!lock.is_sealed());
+    assert!(!lock.is_closed());
+    assert_eq!(
+        crate::with_deadlock_check(lock.seal(), "lock_test".into()).await?,
+        &1
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+#[tokio::test(start_paused = true)]
+async fn acquire_timeout() -> Result<(), Error> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new(0, "main_lock");
+            let timeout = Duration::from_secs(60);
+
+            let mutex = lock.mutex.lock().await;
+            assert_eq!(
+                lock.queue_timeout(timeout).await.err(),
+                Some(Error::AcquireTimeout)
+            );
+            assert!(lock.waiters.lock().is_empty());
+            drop(mutex);
+
+            let write = lock.rwlock.write().await;
+            assert_eq!(
+                lock.read_timeout(timeout).await.err(),
+                Some(Error::AcquireTimeout)
+            );
+            drop(write);
+
+            let read = lock.rwlock.read().await;
+            let q = lock.queue_timeout(timeout).await?;
+            assert_eq!(
+                q.write_timeout(timeout).await.err(),
+                Some(Error::AcquireTimeout)
+            );
+            assert!(lock.try_queue().is_some());
+            drop(read);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test(start_paused = true)]
+async fn acquire_until() -> Result<(), Error> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new(0, "main_lock");
+            let read = lock.rwlock.read().await;
+
+            let deadline = tokio::time::Instant::now() + Duration::from_secs(60);
+            let q = lock.queue_until(deadline.into_std()).await?;
+            assert_eq!(
+                q.write_until(deadline.into_std()).await.err(),
+                Some(Error::AcquireTimeout)
+            );
+            assert!(tokio::time::Instant::now() >= deadline);
+            assert!(lock.try_queue().is_some());
+            drop(read);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn upgrade_detects_changes() -> Result<(), Error> {
+    use tokio::sync::oneshot;
+
+    let lock = QueueRwLock::new(0, "main_lock");
+
+    crate::with_deadlock_check(
+        async {
+            let (_q, changed) = lock.read().await?.upgrade().await?;
+            assert!(!changed);
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await?;
+
+    let (tx, rx) = oneshot::channel();
+
+    let writer = crate::with_deadlock_check(
+        async {
+            let q = lock.queue().await?;
+            tx.send(()).unwrap();
+            tokio::task::yield_now().await;
+            *q.write().await? = 1;
+            Ok(())
+        },
+        "writer".into(),
+    );
+
+    let reader = crate::with_deadlock_check(
+        async {
+            rx.await.unwrap();
+
+            let read = lock.read().await?;
+            assert_eq!(*read, 0);
+
+            let (q, changed) = read.upgrade().await?;
+            assert!(changed);
+            assert_eq!(*q, 1);
+            Ok(())
+        },
+        "reader".into(),
+    );
+
+    let (a, b) = tokio::join!(writer, reader);
+    a.and(b)
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn with_queue_and_write() -> Result<(), Error> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new(vec![1], "main_lock");
+
+            assert_eq!(lock.with_write(|v| v.push(2)).await, Ok(()));
+            assert_eq!(lock.with_queue(|v| v.len()).await, Ok(2));
+            assert!(lock.try_queue().is_some());
+
+            let len = lock
+                .queue_scoped(
+                    |v| v.iter().sum(),
+                    |v, sum| {
+                        v.push(sum);
+                        v.len()
+                    },
+                )
+                .await?;
+
+            assert_eq!(len, 3);
+            assert_eq!(*lock.read().await?, [1, 2, 3]);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn waiters_by_op() -> Result<(), Error> {
+    let lock = QueueRwLock::new(0, "main_lock");
+    let place = lock.enqueue();
+
+    assert_eq!(lock.queue_len(), 1);
+    drop(place);
+    assert_eq!(lock.queue_len(), 0);
+
+    let write = lock.rwlock.write().await;
+
+    let reader =
+        crate::with_deadlock_check(async { lock.read().await.map(|r| *r) }, "reader".into());
+
+    let check = async {
+        while lock.read_waiters() == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        assert_eq!(lock.waiters(), 1);
+        assert_eq!(lock.write_waiters(), 0);
+        drop(write);
+    };
+
+    let (read, _) = tokio::join!(reader, check);
+
+    assert_eq!(read, Ok(0));
+    assert_eq!(lock.waiters(), 0);
+
+    Ok(())
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn cancelled_acquisitions_clean_up() -> Result<(), Error> {
+    use crate::{primitives::task, test_util::held_locks};
+    use tokio::task::yield_now;
+
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new(0, "main_lock");
+            let other = QueueRwLock::new(0, "other_lock");
+            let _other = other.read().await?;
+
+            let assert_clean = || {
+                assert!(task::current().unwrap().awaiting().is_none());
+                assert_eq!(held_locks(), ["other_lock"]);
+                assert_eq!(lock.waiters(), 0);
+            };
+
+            let mutex = lock.mutex.lock().await;
+
+            tokio::select! {
+                biased;
+                _ = lock.queue() => unreachable!(),
+                _ = yield_now() => {}
+            }
+
+            assert_clean();
+            assert_eq!(lock.queue_len(), 0);
+            drop(mutex);
+
+            let write = lock.rwlock.write().await;
+
+            tokio::select! {
+                biased;
+                _ = lock.read() => unreachable!(),
+                _ = yield_now() => {}
+            }
+
+            assert_clean();
+            drop(write);
+
+            let read = lock.rwlock.read().await;
+            let q = lock.queue().await?;
+
+            tokio::select! {
+                biased;
+                _ = q.write() => unreachable!(),
+                _ = yield_now() => {}
+            }
+
+            assert_clean();
+            drop(read);
+
+            // the task can await again.
+            *lock.queue().await?.write().await? = 1;
+            assert_eq!(*lock.read().await?, 1);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn static_lock() -> Result<(), Error> {
+    static LOCK: QueueRwLock<Vec<u32>> =
+        QueueRwLock::const_new(Vec::new(), "static_lock").with_group("static_locks");
+
+    crate::with_deadlock_check(
+        async move {
+            LOCK.queue().await?.write().await?.push(1);
+            assert_eq!(*LOCK.read().await?, [1]);
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn write_if_skips_empty_changes() -> Result<(), Error> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new(vec![1], "main_lock");
+
+            assert!(lock
+                .queue()
+                .await?
+                .write_if(|v| v.is_empty())
+                .await?
+                .is_none());
+            assert!(lock.try_queue().is_some());
+            assert_eq!(lock.last_written_at(), None);
+
+            lock.queue()
+                .await?
+                .write_if(|v| !v.is_empty())
+                .await?
+                .unwrap()
+                .push(2);
+
+            assert_eq!(*lock.read().await?, [1, 2]);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn snapshot_after_write() -> Result<(), Error> {
+    let mut lock = QueueRwLock::new(vec![1], "main_lock").snapshots();
+    let before = lock.snapshot().unwrap();
+
+    crate::with_deadlock_check(
+        async {
+            lock.queue().await?.write().await?.push(2);
+            Ok::<_, Error>(())
+        },
+        "lock_test".into(),
+    )
+    .await?;
+
+    assert_eq!(*before, [1]);
+    assert_eq!(*lock.snapshot().unwrap(), [1, 2]);
+
+    lock.get_mut().push(3);
+    assert!(lock.snapshot().is_none());
+    assert!(QueueRwLock::new(0, "other_lock").snapshot().is_none());
+
+    Ok(())
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn blocking_accessors() -> Result<(), Error> {
+    use std::sync::Arc;
+
+    let lock = Arc::new(QueueRwLock::new(0, "main_lock"));
+    let l = Arc::clone(&lock);
+
+    tokio::task::spawn_blocking(move || {
+        crate::blocking::with_deadlock_check(
+            || {
+                *l.blocking_queue()?.blocking_write()? = 1;
+                assert_eq!(*l.blocking_read()?, 1);
+                Ok::<_, Error>(())
+            },
+            "blocking".into(),
+        )
+    })
+    .await
+    .unwrap()?;
+
+    crate::with_deadlock_check(
+        async move {
+            assert!(matches!(
+                lock.blocking_read(),
+                Err(Error::ForbiddenInAsync(_))
+            ));
+            assert_eq!(*lock.read().await?, 1);
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn blocking_queue_waits_its_turn() -> Result<(), Error> {
+    use std::sync::Arc;
+
+    let lock = Arc::new(QueueRwLock::new(0, "main_lock"));
+
+    crate::with_deadlock_check(
+        async {
+            let q = lock.queue().await?;
+            let head = lock.enqueue();
+            let l = Arc::clone(&lock);
+
+            let blocking = tokio::task::spawn_blocking(move || {
+                crate::blocking::with_deadlock_check(
+                    || l.blocking_queue().map(|q| *q),
+                    "blocking".into(),
+                )
+            });
+
+            while lock.queue_len() < 2 {
+                tokio::task::yield_now().await;
+            }
+
+            drop(q);
+            *head.granted().await?.write().await? = 1;
+
+            assert_eq!(blocking.await.unwrap()?, 1);
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn recursive_reads_are_counted() -> Result<(), Error> {
+    use crate::test_util::held_locks;
+
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new(0, "main_lock").recursive_reads();
+
+            let r1 = lock.read().await?;
+            let r2 = lock.read().await?;
+            assert_eq!(held_locks(), ["main_lock"]);
+
+            drop(r1);
+            assert_eq!(held_locks(), ["main_lock"]);
+
+            drop(r2);
+            assert!(held_locks().is_empty());
+
+            let _r = lock.read().await?;
+            let mut write = Box::pin(lock.rwlock.write());
+
+            tokio::select! {
+                biased;
+                _ = &mut write => unreachable!(),
+                _ = tokio::task::yield_now() => {}
+            }
+
+            // behind the writer.
+            assert_eq!(
+                lock.read().await.err(),
+                Some(Error::RecursiveLock(Default::default()))
+            );
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn queue_into_read() -> Result<(), Error> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new(0, "main_lock");
+            let acquired = task::current()?.stats.lock_stats().acquired;
+            let r = lock.queue().await?.into_read();
+
+            assert_eq!(task::current()?.stats.lock_stats().acquired, acquired + 1);
+            assert_eq!(task::current()?.held()[0].2, "read");
+            assert_eq!(lock.reader_count(), 1);
+            assert!(lock.try_queue().is_some());
+            assert!(lock.rwlock.try_write().is_err());
+            assert_eq!(*r, 0);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn subscribe_to_writes() -> Result<(), Error> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new(0, "main_lock");
+            let mut changes = lock.subscribe();
+
+            assert!(!changes.has_changed().unwrap());
+
+            drop(lock.queue().await?);
+            assert!(!changes.has_changed().unwrap());
+
+            *lock.queue().await?.write().await? = 1;
+            assert_eq!(*changes.borrow_and_update(), 1);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn high_priority_granted_before_low() -> Result<(), Error> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new(0, "main_lock");
+            let q = lock.queue().await?;
+            let head = lock.enqueue();
+            let low = lock.enqueue_with_priority(Priority::Low);
+            let high = lock.enqueue_with_priority(Priority::High);
+
+            assert_eq!(high.position(), 1);
+            drop(q);
+
+            // the newcomers don't pass the waiters.
+            assert!(lock.try_queue().is_none());
+            assert!(lock.try_write().is_none());
+
+            drop(head.granted().await?);
+            assert_eq!(low.position(), 1);
+
+            *high.granted().await?.write().await? = 1;
+            *low.granted().await?.write().await? = 2;
+
+            assert_eq!(*lock.read().await?, 2);
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn high_priority_goes_first() -> Result<(), Error> {
+    use crate::with_deadlock_check;
+    use tokio::task::yield_now;
+
+    let lock = QueueRwLock::new(0, "main_lock");
+    let order = parking_lot::Mutex::new(Vec::new());
+
+    let writer = |name: &'static str, priority| {
+        let (lock, order) = (&lock, &order);
+
+        with_deadlock_check(
+            async move {
+                let _q = lock.queue_with_priority(priority).await?;
+                order.lock().push(name);
+                Ok::<_, Error>(())
+            },
+            name.into(),
+        )
+    };
+
+    let holder = with_deadlock_check(
+        async {
+            let _q = lock.queue().await?;
+
+            while lock.queue_len() < 3 {
+                yield_now().await;
+            }
+
+            Ok(())
+        },
+        "holder".into(),
+    );
+
+    let enqueue = |after: usize| {
+        let lock = &lock;
+
+        async move {
+            while lock.queue_len() < after {
+                yield_now().await;
+            }
+        }
+    };
+
+    let (holder, low, normal, high) = tokio::join!(
+        holder,
+        async {
+            enqueue(0).await;
+            writer("low", Priority::Low).await
+        },
+        async {
+            enqueue(1).await;
+            writer("normal", Priority::Normal).await
+        },
+        async {
+            enqueue(2).await;
+            writer("high", Priority::High).await
+        },
+    );
+
+    holder.and(low).and(normal).and(high)?;
+
+    // the first waiter already waits for the holder.
+    assert_eq!(*order.lock(), ["low", "high", "normal"]);
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn try_write_when_free() -> Result<(), Error> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new(0, "main_lock");
+
+            let q = lock.queue().await?;
+            assert!(lock.try_write().is_none());
+            drop(q);
+
+            let r = lock.read().await?;
+            assert!(lock.try_write().is_none());
+            drop(r);
+
+            *lock.try_write().unwrap() = 1;
+            assert_eq!(*lock.read().await?, 1);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn poisoned_by_panicking_writer() -> Result<(), Error> {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new(vec![1], "main_lock").poisoning();
+            let w = lock.queue().await?.write().await?;
+
+            let r = catch_unwind(AssertUnwindSafe(move || {
+                let mut w = w;
+                w.push(2);
+                panic!("failed halfway");
+            }));
+
+            assert!(r.is_err());
+            assert!(lock.is_poisoned());
+            assert_eq!(lock.read().await.err(), Some(Error::Poisoned));
+            assert_eq!(lock.queue().await.err(), Some(Error::Poisoned));
+
+            lock.clear_poison();
+            assert_eq!(*lock.read().await?, [1, 2]);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn not_poisoned_by_rolled_back_transaction() -> Result<(), Error> {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new(vec![1], "main_lock").poisoning();
+            let w = lock.queue().await?.write_transactional().await?;
+
+            let r = catch_unwind(AssertUnwindSafe(move || {
+                let mut w = w;
+                w.push(2);
+                panic!("failed halfway");
+            }));
+
+            assert!(r.is_err());
+            assert!(!lock.is_poisoned());
+            assert_eq!(*lock.read().await?, [1]);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn version_bumped_by_writes() -> Result<(), Error> {
+    crate::with_deadlock_check(
+        async move {
+            let mut lock = QueueRwLock::new(0, "main_lock");
+            assert_eq!(lock.version(), 0);
+
+            let q = lock.queue().await?;
+            assert_eq!(q.version(), 0);
+
+            *q.write().await? = 1;
+            assert_eq!(lock.read().await?.version(), 1);
+
+            *lock.get_mut() = 2;
+            assert_eq!(lock.version(), 2);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn from_and_into_rwlock() -> Result<(), Error> {
+    let lock = QueueRwLock::from_rwlock(RwLock::new(1), "main_lock");
+
+    crate::with_deadlock_check(
+        async {
+            *lock.queue().await?.write().await? = 2;
+            Ok::<_, Error>(())
+        },
+        "lock_test".into(),
+    )
+    .await?;
+
+    let (rwlock, name) = lock.into_parts();
+
+    assert_eq!(*rwlock.read().await, 2);
+    assert_eq!(name, "main_lock");
+
+    Ok(())
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn write_into_tokio_guard_records_the_write() -> Result<(), Error> {
+    use crate::primitives::locks_held::has_lock_held;
+
+    crate::with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new(1, "main_lock");
+            let mut w = lock.queue().await?.write().await?.into_tokio_guard();
+
+            assert!(!has_lock_held());
+            assert_eq!(lock.version(), 1);
+            assert!(lock.try_queue().is_none());
+
+            *w = 2;
+            drop(w);
+
+            assert_eq!(*lock.read().await?, 2);
+            Ok(())
+        },
+        "write_into_tokio_guard_records_the_write".into(),
+    )
+    .await
+}
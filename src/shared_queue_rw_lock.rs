@@ -0,0 +1,387 @@
+use crate::{
+    Error, QueueRwLock, QueueRwLockQueueGuard, QueueRwLockReadGuard, QueueRwLockWriteGuard,
+};
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    ops::{Deref, DerefMut},
+    sync::Arc,
+    time::Duration,
+};
+
+/// A cheaply clonable handle to a [QueueRwLock], producing owned guards.
+pub struct SharedQueueRwLock<T>(Arc<QueueRwLock<T>>);
+
+impl<T: 'static> SharedQueueRwLock<T> {
+    /// Creates a new instance of an `SharedQueueRwLock<T>` which is unlocked.
+    pub fn new(val: T, lock_name: &'static str) -> Self {
+        Self(Arc::new(QueueRwLock::new(val, lock_name)))
+    }
+
+    /// Returns the underlying `Arc<QueueRwLock<T>>`.
+    pub fn into_arc(self) -> Arc<QueueRwLock<T>> {
+        self.0
+    }
+
+    /// Returns true if both handles point to the same lock.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Enqueue to gain access to the write, returning a guard that
+    /// keeps the lock alive.
+    pub async fn queue_owned(&self) -> Result<OwnedQueueRwLockQueueGuard<T>, Error> {
+        Arc::clone(&self.0).queue_owned().await
+    }
+
+    /// Locks with shared read access, returning a guard that keeps the lock alive.
+    pub async fn read_owned(&self) -> Result<OwnedQueueRwLockReadGuard<T>, Error> {
+        Arc::clone(&self.0).read_owned().await
+    }
+
+    /// Locks with exclusive write access, going through the queue, returning
+    /// a guard that keeps the lock alive.
+    pub async fn write_owned(&self) -> Result<OwnedQueueRwLockWriteGuard<T>, Error> {
+        Arc::clone(&self.0).write_owned().await
+    }
+}
+
+impl<T: 'static> QueueRwLock<T> {
+    /// Same as [queue](Self::queue), returning a guard that keeps the lock alive.
+    pub async fn queue_owned(self: Arc<Self>) -> Result<OwnedQueueRwLockQueueGuard<T>, Error> {
+        let guard = extend(&self).queue().await?;
+        Ok(OwnedQueueRwLockQueueGuard { guard, _lock: self })
+    }
+
+    /// Same as [read](Self::read), returning a guard that keeps the lock alive.
+    pub async fn read_owned(self: Arc<Self>) -> Result<OwnedQueueRwLockReadGuard<T>, Error> {
+        let guard = extend(&self).read().await?;
+        Ok(OwnedQueueRwLockReadGuard { guard, _lock: self })
+    }
+
+    /// Enqueue then locks with exclusive write access, returning a guard that
+    /// keeps the lock alive.
+    pub async fn write_owned(self: Arc<Self>) -> Result<OwnedQueueRwLockWriteGuard<T>, Error> {
+        self.queue_owned().await?.write().await
+    }
+}
+
+impl<T> Clone for SharedQueueRwLock<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<T: Default + 'static> Default for SharedQueueRwLock<T> {
+    fn default() -> Self {
+        Self(Default::default())
+    }
+}
+
+impl<T> Deref for SharedQueueRwLock<T> {
+    type Target = QueueRwLock<T>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> From<Arc<QueueRwLock<T>>> for SharedQueueRwLock<T> {
+    fn from(lock: Arc<QueueRwLock<T>>) -> Self {
+        Self(lock)
+    }
+}
+
+impl<T> From<QueueRwLock<T>> for SharedQueueRwLock<T> {
+    fn from(lock: QueueRwLock<T>) -> Self {
+        Self(Arc::new(lock))
+    }
+}
+
+/// Extends the lifetime of the lock to `'static`.
+///
+/// The guards built from the returned reference must be declared before the
+/// `Arc` in the owned guards so they are dropped first.
+fn extend<T>(lock: &Arc<QueueRwLock<T>>) -> &'static QueueRwLock<T> {
+    // SAFETY: the owned guards hold a clone of the `Arc`, keeping the lock alive
+    // for as long as the borrowed guard exists.
+    unsafe { &*Arc::as_ptr(lock) }
+}
+
+pub struct OwnedQueueRwLockReadGuard<T: 'static> {
+    guard: QueueRwLockReadGuard<'static, T>,
+    _lock: Arc<QueueRwLock<T>>,
+}
+
+impl<T> OwnedQueueRwLockReadGuard<T> {
+    #[cfg(not(feature = "unchecked"))]
+    pub fn elapsed(&self) -> Duration {
+        self.guard.elapsed()
+    }
+
+    pub async fn queue(self) -> Result<OwnedQueueRwLockQueueGuard<T>, Error> {
+        let Self { guard, _lock: lock } = self;
+        let guard = guard.queue().await?;
+
+        Ok(OwnedQueueRwLockQueueGuard { guard, _lock: lock })
+    }
+}
+
+impl<T> Debug for OwnedQueueRwLockReadGuard<T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.guard.fmt(f)
+    }
+}
+
+impl<T> Deref for OwnedQueueRwLockReadGuard<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T> Display for OwnedQueueRwLockReadGuard<T>
+where
+    T: Display,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.guard.fmt(f)
+    }
+}
+
+pub struct OwnedQueueRwLockQueueGuard<T: 'static> {
+    guard: QueueRwLockQueueGuard<'static, T>,
+    _lock: Arc<QueueRwLock<T>>,
+}
+
+impl<T> OwnedQueueRwLockQueueGuard<T> {
+    #[cfg(not(feature = "unchecked"))]
+    pub fn elapsed(&self) -> Duration {
+        self.guard.elapsed()
+    }
+
+    pub fn queued_for(&self) -> Duration {
+        self.guard.queued_for()
+    }
+
+    /// Locks with exclusive write access, releasing the queue.
+    pub async fn write(self) -> Result<OwnedQueueRwLockWriteGuard<T>, Error> {
+        let Self { guard, _lock: lock } = self;
+        let guard = guard.write().await?;
+
+        Ok(OwnedQueueRwLockWriteGuard { guard, _lock: lock })
+    }
+}
+
+impl<T> Debug for OwnedQueueRwLockQueueGuard<T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.guard.fmt(f)
+    }
+}
+
+impl<T> Deref for OwnedQueueRwLockQueueGuard<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T> Display for OwnedQueueRwLockQueueGuard<T>
+where
+    T: Display,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.guard.fmt(f)
+    }
+}
+
+pub struct OwnedQueueRwLockWriteGuard<T: 'static> {
+    guard: QueueRwLockWriteGuard<'static, T>,
+    _lock: Arc<QueueRwLock<T>>,
+}
+
+impl<T> OwnedQueueRwLockWriteGuard<T> {
+    /// Detaches the write from the current task, to hand it to another task
+    /// through a channel. The receiving task takes it back with
+    /// [accept](OwnedQueueRwLockWriteTransfer::accept).
+    pub fn transfer(mut self) -> OwnedQueueRwLockWriteTransfer<T> {
+        self.guard.detach();
+        OwnedQueueRwLockWriteTransfer(self)
+    }
+
+    pub async fn read(self) -> Result<OwnedQueueRwLockReadGuard<T>, Error> {
+        let Self { guard, _lock: lock } = self;
+        let guard = guard.read().await?;
+
+        Ok(OwnedQueueRwLockReadGuard { guard, _lock: lock })
+    }
+
+    pub async fn queue(self) -> Result<OwnedQueueRwLockQueueGuard<T>, Error> {
+        let Self { guard, _lock: lock } = self;
+        let guard = guard.queue().await?;
+
+        Ok(OwnedQueueRwLockQueueGuard { guard, _lock: lock })
+    }
+}
+
+/// An [OwnedQueueRwLockWriteGuard] in transit between two tasks.
+///
+/// The write stays locked, attributed to a `transfer` pseudo task until
+/// accepted, so the deadlock detection and the wait graph still see it.
+pub struct OwnedQueueRwLockWriteTransfer<T: 'static>(OwnedQueueRwLockWriteGuard<T>);
+
+impl<T> OwnedQueueRwLockWriteTransfer<T> {
+    /// Attributes the write to the current task, the held duration being
+    /// measured from now.
+    pub fn accept(mut self) -> Result<OwnedQueueRwLockWriteGuard<T>, Error> {
+        self.0.guard.attach()?;
+        Ok(self.0)
+    }
+}
+
+impl<T> Debug for OwnedQueueRwLockWriteGuard<T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.guard.fmt(f)
+    }
+}
+
+impl<T> Deref for OwnedQueueRwLockWriteGuard<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for OwnedQueueRwLockWriteGuard<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<T> Display for OwnedQueueRwLockWriteGuard<T>
+where
+    T: Display,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.guard.fmt(f)
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn owned_guards_in_struct() -> Result<(), Error> {
+    struct Holder {
+        _guard: OwnedQueueRwLockQueueGuard<usize>,
+    }
+
+    crate::with_deadlock_check(
+        async move {
+            let lock = SharedQueueRwLock::new(0, "main_lock");
+            let holder = Holder {
+                _guard: lock.clone().queue_owned().await?,
+            };
+
+            assert!(lock.try_queue().is_none());
+
+            let mut w = holder._guard.write().await?;
+            *w = 1;
+            drop(w);
+
+            assert_eq!(*lock.read_owned().await?, 1);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn owned_guards_in_spawned_task() -> Result<(), Error> {
+    let lock = Arc::new(QueueRwLock::new(0, "main_lock"));
+    let w = Arc::clone(&lock);
+
+    tokio::spawn(crate::with_deadlock_check(
+        async move {
+            let mut w = w.write_owned().await?;
+            *w = 1;
+            Ok::<_, Error>(())
+        },
+        "writer".into(),
+    ))
+    .await
+    .unwrap()?;
+
+    crate::with_deadlock_check(
+        async move {
+            assert_eq!(*lock.read_owned().await?, 1);
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn transfer_write_guard() -> Result<(), Error> {
+    use crate::{test_util::held_locks, with_deadlock_check};
+
+    let lock = SharedQueueRwLock::new(0, "main_lock");
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    let sender = with_deadlock_check(
+        async {
+            let w = lock.queue_owned().await?.write().await?;
+
+            tx.send(w.transfer()).ok().unwrap();
+            assert!(held_locks().is_empty());
+            assert_eq!(lock.0.lock_data.holders("write"), 1);
+
+            Ok(())
+        },
+        "sender".into(),
+    );
+
+    let receiver = with_deadlock_check(
+        async {
+            let mut w = rx.await.unwrap().accept()?;
+
+            assert_eq!(held_locks(), ["main_lock"]);
+            assert_eq!(lock.0.lock_data.holders("write"), 1);
+            *w = 1;
+
+            Ok(())
+        },
+        "receiver".into(),
+    );
+
+    let (sender, receiver) = tokio::join!(sender, receiver);
+    sender.and(receiver)?;
+
+    with_deadlock_check(
+        async {
+            assert_eq!(*lock.read().await?, 1);
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
@@ -1,11 +1,11 @@
 use crate::{
     primitives::{LockAwaitGuard, LockData, LockHeldGuard},
-    Result,
+    Result, Thresholds,
 };
 use std::ops::{Deref, DerefMut};
 
 pub struct Mutex<T> {
-    lock_data: LockData,
+    pub(crate) lock_data: LockData,
     mutex: tokio::sync::Mutex<T>,
 }
 
@@ -17,6 +17,19 @@ impl<T> Mutex<T> {
         }
     }
 
+    /// Reports the metrics of this lock under `group` instead of its name.
+    pub const fn with_group(mut self, group: &'static str) -> Self {
+        self.lock_data.group = group;
+        self
+    }
+
+    /// Overrides the durations after which waiting for or holding
+    /// this lock is reported in the telemetry.
+    pub const fn with_thresholds(mut self, thresholds: Thresholds) -> Self {
+        self.lock_data.thresholds = thresholds;
+        self
+    }
+
     pub fn get_mut(&mut self) -> &mut T {
         self.mutex.get_mut()
     }
@@ -25,6 +38,11 @@ impl<T> Mutex<T> {
         self.mutex.into_inner()
     }
 
+    /// Returns true if the mutex is locked, by any task.
+    pub fn is_locked(&self) -> bool {
+        self.mutex.try_lock().is_err()
+    }
+
     pub async fn lock(&self) -> Result<MutexGuard<'_, T>> {
         if let Ok(guard) = self.mutex.try_lock() {
             return Ok(MutexGuard {
@@ -46,6 +64,14 @@ pub struct MutexGuard<'a, T> {
     guard: tokio::sync::MutexGuard<'a, T>,
 }
 
+impl<'a, T> MutexGuard<'a, T> {
+    /// Returns the underlying `tokio` guard, ending the deadlock and telemetry
+    /// tracking of this lock even though it stays locked.
+    pub fn into_tokio_guard(self) -> tokio::sync::MutexGuard<'a, T> {
+        self.guard
+    }
+}
+
 impl<T> Deref for MutexGuard<'_, T> {
     type Target = T;
 
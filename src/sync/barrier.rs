@@ -0,0 +1,109 @@
+use crate::{
+    primitives::{LockAwaitGuard, LockData, LockHeldGuard},
+    Result,
+};
+use tokio::sync::BarrierWaitResult;
+
+/// A [tokio::sync::Barrier] participating in the deadlock detection.
+///
+/// A task which has [entered](Self::enter) the barrier holds it until it
+/// waits, so that waiting on the barrier while holding a lock needed by a
+/// participant which has not arrived yet is reported as a deadlock.
+pub struct Barrier {
+    barrier: tokio::sync::Barrier,
+    lock_data: LockData,
+}
+
+impl Barrier {
+    pub fn new(n: usize, name: &'static str) -> Self {
+        Self {
+            barrier: tokio::sync::Barrier::new(n),
+            lock_data: LockData::new(name),
+        }
+    }
+
+    /// Declares the current task as a participant of the barrier, holding it
+    /// until [BarrierGuard::wait].
+    pub fn enter(&self) -> Result<BarrierGuard<'_>> {
+        Ok(BarrierGuard {
+            active: LockHeldGuard::new_no_wait(&self.lock_data, "barrier")?,
+            barrier: self,
+        })
+    }
+
+    /// Waits for all the participants to reach the barrier.
+    ///
+    /// Same as [enter](Self::enter) followed by [BarrierGuard::wait].
+    pub async fn wait(&self) -> Result<BarrierWaitResult> {
+        self.enter()?.wait().await
+    }
+
+    /// The approximate number of tasks waiting on the barrier.
+    #[cfg(not(feature = "unchecked"))]
+    pub fn waiters(&self) -> usize {
+        self.lock_data.waiters()
+    }
+}
+
+pub struct BarrierGuard<'a> {
+    active: LockHeldGuard<'a>,
+    barrier: &'a Barrier,
+}
+
+impl BarrierGuard<'_> {
+    /// Releases the barrier and waits for the other participants.
+    pub async fn wait(self) -> Result<BarrierWaitResult> {
+        let barrier = self.barrier;
+
+        drop(self.active);
+
+        let _wait = LockAwaitGuard::new(&barrier.lock_data, "barrier_wait")?;
+
+        Ok(barrier.barrier.wait().await)
+    }
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn lock_needed_by_a_participant() -> Result<()> {
+    use super::async_mutex::Mutex;
+    use crate::{with_deadlock_check, Error};
+
+    let barrier = Barrier::new(2, "main_barrier");
+    let lock = Mutex::new((), "main_lock");
+
+    let holder = with_deadlock_check(
+        async {
+            let _guard = lock.lock().await?;
+            let entered = barrier.enter()?;
+
+            while barrier.lock_data.holders("barrier") < 2 {
+                tokio::task::yield_now().await;
+            }
+
+            entered.wait().await.map(drop)
+        },
+        "holder".into(),
+    );
+
+    let participant = with_deadlock_check(
+        async {
+            let entered = barrier.enter()?;
+
+            while barrier.waiters() == 0 {
+                tokio::task::yield_now().await;
+            }
+
+            assert_eq!(
+                lock.lock().await.err(),
+                Some(Error::DeadlockDetected(Default::default()))
+            );
+            entered.wait().await.map(drop)
+        },
+        "participant".into(),
+    );
+
+    let (a, b) = tokio::join!(holder, participant);
+
+    a.and(b)
+}
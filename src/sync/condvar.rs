@@ -0,0 +1,122 @@
+use super::mutex::MutexGuard;
+use crate::{
+    primitives::{LockAwaitGuard, LockData, LockHeldGuard},
+    Result,
+};
+use parking_lot::WaitTimeoutResult;
+use std::time::Duration;
+
+/// A [parking_lot::Condvar] pairing with the [Mutex](super::mutex::Mutex) of
+/// this module.
+///
+/// While waiting, the mutex is released in the deadlock detection and the
+/// task awaits the condvar, which shows in the wait graph and the telemetry.
+pub struct Condvar {
+    condvar: parking_lot::Condvar,
+    lock_data: LockData,
+}
+
+impl Condvar {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            condvar: parking_lot::Condvar::new(),
+            lock_data: LockData::new(name),
+        }
+    }
+
+    /// Wakes up one task or thread waiting on this condvar.
+    ///
+    /// Returns true if one has been woken up.
+    pub fn notify_one(&self) -> bool {
+        self.condvar.notify_one()
+    }
+
+    /// Wakes up all the tasks and threads waiting on this condvar.
+    ///
+    /// Returns the number woken up.
+    pub fn notify_all(&self) -> usize {
+        self.condvar.notify_all()
+    }
+
+    /// The approximate number of tasks waiting on this condvar.
+    #[cfg(not(feature = "unchecked"))]
+    pub fn waiters(&self) -> usize {
+        self.lock_data.waiters()
+    }
+
+    /// Releases the mutex of `guard` and blocks until notified, then locks
+    /// the mutex again.
+    pub fn wait<T>(&self, guard: &mut MutexGuard<'_, T>) -> Result<()> {
+        self.wait_imp(guard, |condvar, guard| condvar.wait(guard))
+    }
+
+    /// Same as [wait](Self::wait), waiting at most `timeout` for a notification.
+    pub fn wait_for<T>(
+        &self,
+        guard: &mut MutexGuard<'_, T>,
+        timeout: Duration,
+    ) -> Result<WaitTimeoutResult> {
+        self.wait_imp(guard, |condvar, guard| condvar.wait_for(guard, timeout))
+    }
+
+    fn wait_imp<'a, T, F, R>(&self, guard: &mut MutexGuard<'a, T>, f: F) -> Result<R>
+    where
+        F: FnOnce(&parking_lot::Condvar, &mut parking_lot::MutexGuard<'a, T>) -> R,
+    {
+        // the mutex is untracked, as when forbidden in async.
+        if guard.active.is_none() {
+            return Ok(f(&self.condvar, &mut guard.guard));
+        }
+
+        let wait = LockAwaitGuard::new(&self.lock_data, "condvar_wait")?;
+
+        guard.active = None;
+
+        let result = f(&self.condvar, &mut guard.guard);
+
+        drop(wait);
+        guard.active = Some(LockHeldGuard::new_no_wait(guard.lock_data, "sync_lock")?);
+
+        Ok(result)
+    }
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn wait_releases_the_mutex() -> Result<()> {
+    use super::mutex::Mutex;
+    use crate::Error;
+
+    crate::with_deadlock_check(
+        async move {
+            let mutex = Mutex::new(0, "main_lock");
+            let condvar = Condvar::new("main_condvar");
+            let mut guard = mutex.lock()?;
+
+            assert!(condvar.wait_for(&mut guard, Duration::ZERO)?.timed_out());
+            assert_eq!(
+                mutex.lock().err(),
+                Some(Error::RecursiveLock(Default::default()))
+            );
+
+            std::thread::scope(|s| {
+                s.spawn(|| {
+                    while !condvar.notify_one() {
+                        std::thread::yield_now();
+                    }
+                });
+
+                condvar.wait(&mut guard)
+            })?;
+
+            *guard = 1;
+            assert_eq!(condvar.waiters(), 0);
+            drop(guard);
+
+            assert_eq!(*mutex.lock()?, 1);
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
@@ -1,2 +1,90 @@
+//! Locks tracked by the deadlock detection.
+//!
+//! With the `send_guard` feature, the guards of the synchronous [Mutex](mutex::Mutex)
+//! and [RwLock](rwlock::RwLock) are `Send` and may be held in futures moving between
+//! the threads of a work-stealing executor. The deadlock detection follows the
+//! task holding the guard rather than the thread, so it needs no update when
+//! the future migrates.
+
+use crate::{
+    primitives::{task, LockAwaitGuard, LockData, LockHeldGuard},
+    Result,
+};
+
 pub mod async_mutex;
+pub mod barrier;
+pub mod condvar;
 pub mod mutex;
+pub mod notify;
+pub mod once;
+pub mod once_lock;
+pub mod reentrant_mutex;
+pub mod rwlock;
+pub mod semaphore;
+pub mod watch;
+
+/// Fails if a lock marked as forbidden in async is acquired from a thread of
+/// a tokio runtime, such as a worker running a `tokio::spawn` task, with or
+/// without a `with_deadlock_check`.
+///
+/// The blocking sections of a runtime, such as `spawn_blocking` or
+/// `block_in_place`, must run inside a
+/// [blocking::with_deadlock_check](crate::blocking::with_deadlock_check)
+/// to acquire the lock.
+#[track_caller]
+#[cfg_attr(feature = "unchecked", allow(unused_variables))]
+fn check_forbid_async(lock_data: &LockData, op: &'static str) -> Result<()> {
+    #[cfg(not(feature = "unchecked"))]
+    if task::on_runtime_thread() {
+        return Err(crate::Error::forbidden_in_async(
+            lock_data,
+            op,
+            std::panic::Location::caller(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Acquires a lock forbidden in async with `lock`, which may wait for as long
+/// as needed: tracked inside a
+/// [blocking::with_deadlock_check](crate::blocking::with_deadlock_check),
+/// untracked elsewhere.
+fn lock_forbidden<'a, G, F>(
+    lock_data: &'a LockData,
+    op: &'static str,
+    lock: F,
+) -> Result<(Option<LockHeldGuard<'a>>, G)>
+where
+    F: FnOnce() -> Result<G>,
+{
+    if task::try_with(|_| ()).is_err() {
+        return Ok((None, lock()?));
+    }
+
+    let wait = LockAwaitGuard::new(lock_data, op)?;
+    let guard = lock()?;
+
+    Ok((Some(LockHeldGuard::new(wait)?), guard))
+}
+
+/// Same as [lock_forbidden] for a lock already acquired without waiting.
+fn held_forbidden<'a>(
+    lock_data: &'a LockData,
+    op: &'static str,
+) -> Result<Option<LockHeldGuard<'a>>> {
+    match task::try_with(|_| ()) {
+        Ok(()) => Ok(Some(LockHeldGuard::new_no_wait(lock_data, op)?)),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(all(test, feature = "send_guard"))]
+#[test]
+fn guards_are_send() {
+    fn assert_send<T: Send>() {}
+
+    assert_send::<mutex::MutexGuard<'static, ()>>();
+    assert_send::<rwlock::RwLockReadGuard<'static, ()>>();
+    assert_send::<rwlock::RwLockWriteGuard<'static, ()>>();
+}
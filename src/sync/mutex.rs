@@ -1,25 +1,98 @@
 use crate::{
     primitives::{LockAwaitGuard, LockData, LockHeldGuard},
-    Error, Result,
+    Config, Error, Result, Thresholds,
 };
 use std::{
     ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, Ordering::Relaxed},
     time::Duration,
 };
 
 pub struct Mutex<T> {
-    lock_data: LockData,
+    forbid_async: bool,
+    pub(crate) lock_data: LockData,
     mutex: parking_lot::Mutex<T>,
+    poisoned: AtomicBool,
+    poisoning: bool,
+    timeout: Option<Duration>,
 }
 
 impl<T> Mutex<T> {
     pub const fn new(value: T, name: &'static str) -> Self {
         Self {
+            forbid_async: false,
             lock_data: LockData::new(name),
             mutex: parking_lot::Mutex::new(value),
+            poisoned: AtomicBool::new(false),
+            poisoning: false,
+            timeout: None,
         }
     }
 
+    /// Marks the lock as guarding slow operations which must not run in
+    /// async code.
+    ///
+    /// Locking from a thread of a tokio runtime then returns
+    /// [Error::ForbiddenInAsync] immediately, while locking from a plain
+    /// thread waits for as long as needed, tracked inside a
+    /// [blocking::with_deadlock_check](crate::blocking::with_deadlock_check).
+    pub const fn forbid_async(mut self) -> Self {
+        self.forbid_async = true;
+        self
+    }
+
+    /// Poisons the mutex when a guard is dropped during a panic, as the value
+    /// may be left partially changed: locking then fails with
+    /// [Error::Poisoned] until [clear_poison](Self::clear_poison).
+    pub const fn poisoning(mut self) -> Self {
+        self.poisoning = true;
+        self
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Relaxed)
+    }
+
+    /// Accepts the value as is after a panic, for instance once repaired
+    /// with [get_mut](Self::get_mut).
+    pub fn clear_poison(&self) {
+        self.poisoned.store(false, Relaxed);
+    }
+
+    fn check_poisoned(&self) -> Result<()> {
+        match self.is_poisoned() {
+            true => Err(Error::Poisoned),
+            false => Ok(()),
+        }
+    }
+
+    fn poison(&self) -> PoisonGuard<'_> {
+        PoisonGuard(self.poisoning.then_some(&self.poisoned))
+    }
+
+    /// Overrides [Config::sync_lock_timeout] for this lock.
+    pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout.unwrap_or(Config::get().sync_lock_timeout)
+    }
+
+    /// Reports the metrics of this lock under `group` instead of its name.
+    pub const fn with_group(mut self, group: &'static str) -> Self {
+        self.lock_data.group = group;
+        self
+    }
+
+    /// Overrides the durations after which waiting for or holding
+    /// this lock is reported in the telemetry.
+    pub const fn with_thresholds(mut self, thresholds: Thresholds) -> Self {
+        self.lock_data.thresholds = thresholds;
+        self
+    }
+
     pub fn get_mut(&mut self) -> &mut T {
         self.mutex.get_mut()
     }
@@ -28,29 +101,119 @@ impl<T> Mutex<T> {
         self.mutex.into_inner()
     }
 
+    /// Returns true if the mutex is locked, by any task or thread.
+    pub fn is_locked(&self) -> bool {
+        self.mutex.is_locked()
+    }
+
+    /// The approximate number of tasks waiting for this lock.
+    #[cfg(not(feature = "unchecked"))]
+    pub fn waiters(&self) -> usize {
+        self.lock_data.waiters()
+    }
+
+    /// Locks the mutex, waiting at most the [with_timeout](Self::with_timeout)
+    /// of the lock or else [Config::sync_lock_timeout], 250 ms by default.
+    #[track_caller]
     pub fn lock(&self) -> Result<MutexGuard<'_, T>> {
+        self.check_poisoned()?;
+
+        if self.forbid_async {
+            super::check_forbid_async(&self.lock_data, "sync_lock")?;
+
+            let (active, guard) =
+                super::lock_forbidden(&self.lock_data, "sync_lock", || Ok(self.mutex.lock()))?;
+
+            return Ok(MutexGuard {
+                lock_data: &self.lock_data,
+                poison: self.poison(),
+                active,
+                guard,
+            });
+        }
+
         if let Some(guard) = self.mutex.try_lock() {
             return Ok(MutexGuard {
-                _active: LockHeldGuard::new_no_wait(&self.lock_data, "sync_lock")?,
+                lock_data: &self.lock_data,
+                poison: self.poison(),
+                active: Some(LockHeldGuard::new_no_wait(&self.lock_data, "sync_lock")?),
                 guard,
             });
         }
 
         let wait = LockAwaitGuard::new(&self.lock_data, "sync_lock")?;
 
-        match self.mutex.try_lock_for(Duration::from_millis(250)) {
+        match self.mutex.try_lock_for(self.timeout()) {
             Some(guard) => Ok(MutexGuard {
-                _active: LockHeldGuard::new(wait)?,
+                lock_data: &self.lock_data,
+                poison: self.poison(),
+                active: Some(LockHeldGuard::new(wait)?),
                 guard,
             }),
-            None => Err(Error::SyncLockForTooLong),
+            None => Err(Error::sync_lock_for_too_long(&self.lock_data, "sync_lock")),
+        }
+    }
+
+    /// Locks the mutex if it is free, returning `None` without waiting otherwise.
+    #[track_caller]
+    pub fn try_lock(&self) -> Result<Option<MutexGuard<'_, T>>> {
+        self.check_poisoned()?;
+
+        if self.forbid_async {
+            super::check_forbid_async(&self.lock_data, "sync_lock")?;
         }
+
+        let Some(guard) = self.mutex.try_lock() else {
+            return Ok(None);
+        };
+
+        let active = match self.forbid_async {
+            true => super::held_forbidden(&self.lock_data, "sync_lock")?,
+            false => Some(LockHeldGuard::new_no_wait(&self.lock_data, "sync_lock")?),
+        };
+
+        Ok(Some(MutexGuard {
+            active,
+            guard,
+            lock_data: &self.lock_data,
+            poison: self.poison(),
+        }))
     }
 }
 
 pub struct MutexGuard<'a, T> {
-    _active: LockHeldGuard<'a>,
-    guard: parking_lot::MutexGuard<'a, T>,
+    /// First to drop, before the mutex is unlocked.
+    poison: PoisonGuard<'a>,
+
+    /// Untracked for a mutex forbidden in async locked outside of a
+    /// blocking deadlock check.
+    pub(super) active: Option<LockHeldGuard<'a>>,
+    pub(super) guard: parking_lot::MutexGuard<'a, T>,
+    pub(super) lock_data: &'a LockData,
+}
+
+impl<'a, T> MutexGuard<'a, T> {
+    /// Returns the underlying `parking_lot` guard.
+    ///
+    /// The lock is no longer tracked from this point: the deadlock detection
+    /// and the telemetry consider the lock released while the returned guard
+    /// is still holding it.
+    pub fn into_parking_lot_guard(self) -> parking_lot::MutexGuard<'a, T> {
+        self.guard
+    }
+
+    /// Makes a guard on a part of the locked value, holding the lock
+    /// tracked until dropped.
+    pub fn map<U: ?Sized, F>(s: Self, f: F) -> MappedMutexGuard<'a, U>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        MappedMutexGuard {
+            _active: s.active,
+            guard: parking_lot::MutexGuard::map(s.guard, f),
+            _poison: s.poison,
+        }
+    }
 }
 
 impl<T> Deref for MutexGuard<'_, T> {
@@ -68,3 +231,153 @@ impl<T> DerefMut for MutexGuard<'_, T> {
         &mut self.guard
     }
 }
+
+pub struct MappedMutexGuard<'a, T: ?Sized> {
+    _poison: PoisonGuard<'a>,
+    _active: Option<LockHeldGuard<'a>>,
+    guard: parking_lot::MappedMutexGuard<'a, T>,
+}
+
+/// Poisons the flag of a [poisoning](Mutex::poisoning) mutex if dropped
+/// during a panic, while the mutex is still locked.
+struct PoisonGuard<'a>(Option<&'a AtomicBool>);
+
+impl Drop for PoisonGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(poisoned) = self.0 {
+            if std::thread::panicking() {
+                poisoned.store(true, Relaxed);
+            }
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for MappedMutexGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized> DerefMut for MappedMutexGuard<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn poisoned_by_panicking_guard() -> Result<()> {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    crate::with_deadlock_check(
+        async move {
+            let lock = Mutex::new(vec![1], "main_lock").poisoning();
+            let guard = lock.lock()?;
+
+            let r = catch_unwind(AssertUnwindSafe(move || {
+                let mut guard = guard;
+                guard.push(2);
+                panic!("failed halfway");
+            }));
+
+            assert!(r.is_err());
+            assert!(lock.is_poisoned());
+            assert_eq!(lock.lock().err(), Some(Error::Poisoned));
+
+            lock.clear_poison();
+            assert_eq!(*lock.lock()?, [1, 2]);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn lock_times_out_after_its_own_timeout() -> Result<()> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = Mutex::new(0, "main_lock").with_timeout(Duration::ZERO);
+            let _other = lock.mutex.lock();
+
+            assert_eq!(
+                lock.lock().err(),
+                Some(Error::SyncLockForTooLong(Default::default()))
+            );
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[test]
+fn forbid_async_untracked_outside_async() -> Result<()> {
+    let lock = Mutex::new(0, "main_lock")
+        .forbid_async()
+        .with_timeout(Duration::ZERO);
+
+    let guard = lock.lock()?;
+
+    std::thread::scope(|s| {
+        let other = s.spawn(|| lock.lock().map(|mut g| *g += 1));
+
+        // waits past the timeout, which only bounds the tracked waits.
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(lock.waiters(), 0);
+        drop(guard);
+
+        other.join().unwrap()
+    })?;
+
+    assert_eq!(*lock.lock()?, 1);
+    Ok(())
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[test]
+fn forbid_async_in_blocking_deadlock_check() -> Result<()> {
+    let lock = Mutex::new(0, "main_lock").forbid_async();
+
+    crate::blocking::with_deadlock_check(
+        || {
+            let mut guard = lock.lock()?;
+
+            *guard = 1;
+            assert_eq!(lock.lock_data.holders("sync_lock"), 1);
+            drop(guard);
+
+            assert_eq!(*lock.try_lock()?.unwrap(), 1);
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn forbid_async_on_a_runtime_thread() -> Result<()> {
+    static LOCK: Mutex<i32> = Mutex::new(0, "main_lock").forbid_async();
+
+    let spawned = tokio::spawn(async { LOCK.lock().map(drop) }).await.unwrap();
+    assert!(matches!(spawned, Err(Error::ForbiddenInAsync(_))));
+
+    tokio::task::spawn_blocking(|| {
+        crate::blocking::with_deadlock_check(
+            || LOCK.lock().map(|mut guard| *guard = 1),
+            "lock_test".into(),
+        )
+    })
+    .await
+    .unwrap()?;
+
+    assert_eq!(LOCK.mutex.try_lock().map(|guard| *guard), Some(1));
+    Ok(())
+}
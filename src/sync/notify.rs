@@ -0,0 +1,72 @@
+use crate::{
+    primitives::{LockAwaitGuard, LockData},
+    Result,
+};
+
+/// A [tokio::sync::Notify] showing the tasks waiting for a notification as
+/// awaiting it, in the wait graph, the deadlock errors and the telemetry.
+pub struct Notify {
+    lock_data: LockData,
+    notify: tokio::sync::Notify,
+}
+
+impl Notify {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            lock_data: LockData::new(name),
+            notify: tokio::sync::Notify::const_new(),
+        }
+    }
+
+    /// Waits for a notification.
+    ///
+    /// # Cancel safety
+    ///
+    /// Dropping the future before it completes stops the wait and releases
+    /// the task, without consuming a notification.
+    pub async fn notified(&self) -> Result<()> {
+        let _wait = LockAwaitGuard::new(&self.lock_data, "notify")?;
+
+        self.notify.notified().await;
+        Ok(())
+    }
+
+    /// Wakes up one task waiting, or the next one to wait.
+    pub fn notify_one(&self) {
+        self.notify.notify_one()
+    }
+
+    /// Wakes up all the tasks waiting.
+    pub fn notify_waiters(&self) {
+        self.notify.notify_waiters()
+    }
+
+    /// The approximate number of tasks waiting for a notification.
+    #[cfg(not(feature = "unchecked"))]
+    pub fn waiters(&self) -> usize {
+        self.lock_data.waiters()
+    }
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn waiting_task_in_the_wait_graph() -> Result<()> {
+    let notify = Notify::new("main_notify");
+
+    let waiter = crate::with_deadlock_check(notify.notified(), "waiter".into());
+
+    let notifier = async {
+        while notify.waiters() == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let dot = crate::generate_wait_graph_dot();
+
+        assert!(dot.contains("[label=\"notify\", style=dashed]"));
+        assert!(dot.contains("[label=\"main_notify\"]"));
+
+        notify.notify_one();
+    };
+
+    tokio::join!(waiter, notifier).0
+}
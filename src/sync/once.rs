@@ -0,0 +1,81 @@
+use crate::{
+    primitives::{LockAwaitGuard, LockData, LockHeldGuard},
+    Result,
+};
+use std::sync::OnceState;
+
+/// A [std::sync::Once] participating in the deadlock detection: the task
+/// running the initializer holds the `Once` and the others wait for it.
+pub struct Once {
+    lock_data: LockData,
+    once: std::sync::Once,
+}
+
+impl Once {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            lock_data: LockData::new(name),
+            once: std::sync::Once::new(),
+        }
+    }
+
+    /// Runs `f` if it is the first call, otherwise blocks until the
+    /// first call completes.
+    pub fn call_once<F: FnOnce()>(&self, f: F) -> Result<()> {
+        if self.once.is_completed() {
+            return Ok(());
+        }
+
+        let wait = LockAwaitGuard::new(&self.lock_data, "once")?;
+
+        self.once.call_once(|| {
+            let _held = LockHeldGuard::new(wait).ok();
+            f()
+        });
+
+        Ok(())
+    }
+
+    /// Same as [call_once](Self::call_once) but ignores the poisoning of a
+    /// previous initializer which panicked.
+    pub fn call_once_force<F: FnOnce(&OnceState)>(&self, f: F) -> Result<()> {
+        if self.once.is_completed() {
+            return Ok(());
+        }
+
+        let wait = LockAwaitGuard::new(&self.lock_data, "once")?;
+
+        self.once.call_once_force(|state| {
+            let _held = LockHeldGuard::new(wait).ok();
+            f(state)
+        });
+
+        Ok(())
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.once.is_completed()
+    }
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn recursive_call_once() -> Result<()> {
+    use crate::Error;
+
+    crate::with_deadlock_check(
+        async move {
+            let once = Once::new("main_once");
+            let mut inner = None;
+
+            once.call_once(|| inner = Some(once.call_once(|| {})))?;
+
+            assert_eq!(inner, Some(Err(Error::RecursiveLock(Default::default()))));
+            assert!(once.is_completed());
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
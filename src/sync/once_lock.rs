@@ -0,0 +1,76 @@
+use crate::{
+    primitives::{LockAwaitGuard, LockData, LockHeldGuard},
+    Result,
+};
+use once_cell::sync::OnceCell;
+
+/// A blocking [OnceCell] participating in the deadlock detection: the task
+/// running the initializer holds the cell and the others wait for it.
+///
+/// Two tasks each initializing a cell which needs the cell of the other are
+/// reported as [Error::DeadlockDetected](crate::Error::DeadlockDetected), and
+/// a cell needed by its own initializer as [Error::RecursiveLock](crate::Error::RecursiveLock).
+pub struct OnceLock<T> {
+    cell: OnceCell<T>,
+    lock_data: LockData,
+}
+
+impl<T> OnceLock<T> {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            cell: OnceCell::new(),
+            lock_data: LockData::new(name),
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        self.cell.get()
+    }
+
+    /// Returns the value, running `f` if it is the first call, otherwise
+    /// blocking until the first call completes.
+    pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> Result<&T> {
+        if let Some(v) = self.cell.get() {
+            return Ok(v);
+        }
+
+        let wait = LockAwaitGuard::new(&self.lock_data, "once")?;
+
+        Ok(self.cell.get_or_init(|| {
+            let _held = LockHeldGuard::new(wait).ok();
+            f()
+        }))
+    }
+
+    pub fn into_inner(self) -> Option<T> {
+        self.cell.into_inner()
+    }
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn cells_needed_by_their_initializers() -> Result<()> {
+    use crate::Error;
+
+    static A: OnceLock<Result<u32>> = OnceLock::new("a");
+    static B: OnceLock<Result<u32>> = OnceLock::new("b");
+
+    crate::with_deadlock_check(
+        async move {
+            let a = A.get_or_init(|| {
+                let b = B.get_or_init(|| A.get_or_init(|| Ok(0))?.clone())?;
+                b.clone()
+            })?;
+
+            assert_eq!(*a, Err(Error::RecursiveLock(Default::default())));
+            assert_eq!(
+                B.get(),
+                Some(&Err(Error::RecursiveLock(Default::default())))
+            );
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
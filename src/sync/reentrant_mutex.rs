@@ -0,0 +1,216 @@
+use crate::{
+    primitives::{task, LockAwaitGuard, LockData, LockHeldGuard, Task},
+    Config, Error, Result, Thresholds,
+};
+use parking_lot::Mutex;
+use std::{
+    ops::Deref,
+    sync::{
+        atomic::{AtomicUsize, Ordering::Relaxed},
+        Arc,
+    },
+};
+
+/// A [parking_lot::ReentrantMutex] which the task holding it may lock again.
+///
+/// Several tasks may run on the thread holding it: only the task which
+/// took the outermost lock may lock it again, the others fail with
+/// [Error::DeadlockDetected] since waiting would block that task forever.
+/// Only the outermost lock is tracked: the nested ones never fail with
+/// [Error::RecursiveLock] and only count in the [depth](Self::depth).
+pub struct ReentrantMutex<T> {
+    depth: AtomicUsize,
+    lock_data: LockData,
+    /// The task of the outermost lock, `None` when unlocked or locked
+    /// outside of a deadlock check future.
+    owner: Mutex<Option<Arc<Task>>>,
+    mutex: parking_lot::ReentrantMutex<T>,
+}
+
+impl<T> ReentrantMutex<T> {
+    pub const fn new(value: T, name: &'static str) -> Self {
+        Self {
+            depth: AtomicUsize::new(0),
+            lock_data: LockData::new(name),
+            owner: Mutex::new(None),
+            mutex: parking_lot::ReentrantMutex::new(value),
+        }
+    }
+
+    /// Reports the metrics of this lock under `group` instead of its name.
+    pub const fn with_group(mut self, group: &'static str) -> Self {
+        self.lock_data.group = group;
+        self
+    }
+
+    /// Overrides the durations after which waiting for or holding
+    /// this lock is reported in the telemetry.
+    pub const fn with_thresholds(mut self, thresholds: Thresholds) -> Self {
+        self.lock_data.thresholds = thresholds;
+        self
+    }
+
+    /// The number of nested locks held on this mutex, 0 if unlocked.
+    pub fn depth(&self) -> usize {
+        self.depth.load(Relaxed)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.mutex.into_inner()
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.mutex.is_locked()
+    }
+
+    /// Locks the mutex, waiting at most [Config::sync_lock_timeout],
+    /// 250 ms by default, unless already held by the current task.
+    pub fn lock(&self) -> Result<ReentrantMutexGuard<'_, T>> {
+        if self.mutex.is_owned_by_current_thread() {
+            let owner = self.owner.lock().clone();
+
+            if let (Some(owner), Ok(task)) = (owner, task::current()) {
+                if !Arc::ptr_eq(&owner, &task) {
+                    return Err(Error::deadlock_detected(
+                        &self.lock_data,
+                        "sync_lock",
+                        &owner.name(),
+                    ));
+                }
+            }
+
+            return Ok(self.guard(None, self.mutex.lock()));
+        }
+
+        if let Some(guard) = self.mutex.try_lock() {
+            let active = LockHeldGuard::new_no_wait(&self.lock_data, "sync_lock")?;
+            return Ok(self.outer_guard(active, guard));
+        }
+
+        let wait = LockAwaitGuard::new(&self.lock_data, "sync_lock")?;
+
+        match self.mutex.try_lock_for(Config::get().sync_lock_timeout) {
+            Some(guard) => Ok(self.outer_guard(LockHeldGuard::new(wait)?, guard)),
+            None => Err(Error::sync_lock_for_too_long(&self.lock_data, "sync_lock")),
+        }
+    }
+
+    fn guard<'a>(
+        &'a self,
+        active: Option<LockHeldGuard<'a>>,
+        guard: parking_lot::ReentrantMutexGuard<'a, T>,
+    ) -> ReentrantMutexGuard<'a, T> {
+        self.depth.fetch_add(1, Relaxed);
+
+        ReentrantMutexGuard {
+            _active: active,
+            depth: &self.depth,
+            guard,
+            owner: &self.owner,
+        }
+    }
+
+    fn outer_guard<'a>(
+        &'a self,
+        active: LockHeldGuard<'a>,
+        guard: parking_lot::ReentrantMutexGuard<'a, T>,
+    ) -> ReentrantMutexGuard<'a, T> {
+        *self.owner.lock() = task::current().ok();
+        self.guard(Some(active), guard)
+    }
+}
+
+pub struct ReentrantMutexGuard<'a, T> {
+    /// Only set on the outermost guard, which ends the tracking when dropped
+    /// even before the nested ones.
+    _active: Option<LockHeldGuard<'a>>,
+    depth: &'a AtomicUsize,
+    guard: parking_lot::ReentrantMutexGuard<'a, T>,
+    owner: &'a Mutex<Option<Arc<Task>>>,
+}
+
+impl<T> Deref for ReentrantMutexGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T> Drop for ReentrantMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        if self.depth.fetch_sub(1, Relaxed) == 1 {
+            *self.owner.lock() = None;
+        }
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn nested_locks() -> Result<()> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = ReentrantMutex::new(1, "main_lock");
+            let outer = lock.lock()?;
+            let inner = lock.lock()?;
+
+            assert_eq!(*inner, 1);
+            assert_eq!(lock.depth(), 2);
+
+            drop(outer);
+            drop(inner);
+
+            assert_eq!(lock.depth(), 0);
+            assert!(!lock.is_locked());
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test(flavor = "current_thread")]
+async fn other_task_on_the_same_thread() -> Result<()> {
+    use crate::with_deadlock_check;
+
+    let lock = ReentrantMutex::new(1, "main_lock");
+    let (locked, unlocked) = (tokio::sync::Notify::new(), tokio::sync::Notify::new());
+
+    let holder = with_deadlock_check(
+        async {
+            let outer = lock.lock()?;
+            locked.notify_one();
+            unlocked.notified().await;
+
+            let inner = lock.lock()?;
+            assert_eq!(lock.depth(), 2);
+
+            drop((inner, outer));
+            Ok(())
+        },
+        "holder".into(),
+    );
+
+    let other = with_deadlock_check(
+        async {
+            locked.notified().await;
+
+            assert_eq!(
+                lock.lock().err(),
+                Some(Error::DeadlockDetected(Default::default()))
+            );
+            assert_eq!(lock.depth(), 1);
+
+            unlocked.notify_one();
+            Ok(())
+        },
+        "other".into(),
+    );
+
+    let (a, b) = tokio::join!(holder, other);
+
+    a.and(b)
+}
@@ -0,0 +1,613 @@
+use crate::{
+    primitives::{LockAwaitGuard, LockData, LockHeldGuard, Optimistic},
+    Config, Error, Result, Thresholds,
+};
+use bytemuck::Pod;
+use std::{
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    ptr,
+    time::Duration,
+};
+
+pub struct RwLock<T> {
+    forbid_async: bool,
+    pub(crate) lock_data: LockData,
+    optimistic: Option<Optimistic<T>>,
+    rwlock: parking_lot::RwLock<T>,
+    timeout: Option<Duration>,
+}
+
+impl<T> RwLock<T> {
+    pub const fn new(value: T, name: &'static str) -> Self {
+        Self {
+            forbid_async: false,
+            lock_data: LockData::new(name),
+            optimistic: None,
+            rwlock: parking_lot::RwLock::new(value),
+            timeout: None,
+        }
+    }
+
+    /// Marks the lock as guarding slow operations which must not run in
+    /// async code.
+    ///
+    /// Locking from a thread of a tokio runtime then returns
+    /// [Error::ForbiddenInAsync] immediately, while [read](Self::read) and
+    /// [write](Self::write) from a plain thread wait for as long as needed,
+    /// tracked inside a
+    /// [blocking::with_deadlock_check](crate::blocking::with_deadlock_check).
+    pub const fn forbid_async(mut self) -> Self {
+        self.forbid_async = true;
+        self
+    }
+
+    /// Keeps a copy of the value, updated at the end of each write, for
+    /// [read_optimistic](Self::read_optimistic).
+    ///
+    /// The value is [Pod] so it can be copied as plain bytes while a write
+    /// is being published.
+    pub fn optimistic(mut self) -> Self
+    where
+        T: Pod,
+    {
+        self.optimistic = Some(Optimistic::new(self.rwlock.get_mut()));
+        self
+    }
+
+    /// Copies the value without taking the read lock, retrying while a
+    /// write is being published.
+    ///
+    /// Returns `None` if the lock has not been built with
+    /// [optimistic](Self::optimistic) or if the value has been changed
+    /// through [get_mut](Self::get_mut) since the last write.
+    pub fn read_optimistic(&self) -> Option<T>
+    where
+        T: Pod,
+    {
+        self.optimistic.as_ref()?.read()
+    }
+
+    /// Overrides [Config::sync_lock_timeout] for this lock.
+    pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout.unwrap_or(Config::get().sync_lock_timeout)
+    }
+
+    /// Reports the metrics of this lock under `group` instead of its name.
+    pub const fn with_group(mut self, group: &'static str) -> Self {
+        self.lock_data.group = group;
+        self
+    }
+
+    /// Overrides the durations after which waiting for or holding
+    /// this lock is reported in the telemetry.
+    pub const fn with_thresholds(mut self, thresholds: Thresholds) -> Self {
+        self.lock_data.thresholds = thresholds;
+        self
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        if let Some(optimistic) = &self.optimistic {
+            optimistic.set_dirty();
+        }
+
+        self.rwlock.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.rwlock.into_inner()
+    }
+
+    /// Returns true if the lock is held, in read or write, by any task or thread.
+    pub fn is_locked(&self) -> bool {
+        self.rwlock.is_locked()
+    }
+
+    /// Returns true if the lock is held in write.
+    pub fn is_locked_exclusive(&self) -> bool {
+        self.rwlock.is_locked_exclusive()
+    }
+
+    /// The approximate number of tasks waiting for this lock.
+    #[cfg(not(feature = "unchecked"))]
+    pub fn waiters(&self) -> usize {
+        self.lock_data.waiters()
+    }
+
+    /// Locks with shared read access, waiting at most
+    /// the [with_timeout](Self::with_timeout) of the lock or else
+    /// [Config::sync_lock_timeout], 250 ms by default.
+    #[track_caller]
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, T>> {
+        if self.forbid_async {
+            super::check_forbid_async(&self.lock_data, "sync_read")?;
+
+            let (_active, guard) =
+                super::lock_forbidden(&self.lock_data, "sync_read", || Ok(self.rwlock.read()))?;
+
+            return Ok(RwLockReadGuard { _active, guard });
+        }
+
+        self.read_within(self.timeout(), || {
+            Error::sync_lock_for_too_long(&self.lock_data, "sync_read")
+        })
+    }
+
+    /// Locks with shared read access, returning
+    /// [Error::AcquireTimeout] if it cannot be acquired within `timeout`.
+    ///
+    /// Unless the lock is [forbidden in async](Self::forbid_async), it must be
+    /// acquired inside a `with_deadlock_check` future or a
+    /// [blocking::with_deadlock_check](crate::blocking::with_deadlock_check),
+    /// failing with [Error::NotDeadlockCheckFuture] otherwise.
+    #[track_caller]
+    pub fn read_for(&self, timeout: Duration) -> Result<RwLockReadGuard<'_, T>> {
+        self.read_within(timeout, || {
+            Error::acquire_timeout(&self.lock_data, "sync_read", timeout)
+        })
+    }
+
+    /// Same as [read_for](Self::read_for), failing with `timed_out()` on expiry.
+    #[track_caller]
+    fn read_within<F>(&self, timeout: Duration, timed_out: F) -> Result<RwLockReadGuard<'_, T>>
+    where
+        F: FnOnce() -> Error,
+    {
+        if self.forbid_async {
+            super::check_forbid_async(&self.lock_data, "sync_read")?;
+
+            let (_active, guard) = super::lock_forbidden(&self.lock_data, "sync_read", || {
+                self.rwlock.try_read_for(timeout).ok_or_else(timed_out)
+            })?;
+
+            return Ok(RwLockReadGuard { _active, guard });
+        }
+
+        if let Some(guard) = self.rwlock.try_read() {
+            return Ok(RwLockReadGuard {
+                _active: Some(LockHeldGuard::new_no_wait(&self.lock_data, "sync_read")?),
+                guard,
+            });
+        }
+
+        let wait = LockAwaitGuard::new(&self.lock_data, "sync_read")?;
+
+        match self.rwlock.try_read_for(timeout) {
+            Some(guard) => Ok(RwLockReadGuard {
+                _active: Some(LockHeldGuard::new(wait)?),
+                guard,
+            }),
+            None => Err(timed_out()),
+        }
+    }
+
+    /// Locks with exclusive write access, waiting at most
+    /// the [with_timeout](Self::with_timeout) of the lock or else
+    /// [Config::sync_lock_timeout], 250 ms by default.
+    #[track_caller]
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, T>> {
+        if self.forbid_async {
+            super::check_forbid_async(&self.lock_data, "sync_write")?;
+
+            let (_active, guard) =
+                super::lock_forbidden(&self.lock_data, "sync_write", || Ok(self.rwlock.write()))?;
+
+            return Ok(RwLockWriteGuard {
+                _active,
+                guard,
+                optimistic: self.optimistic.as_ref(),
+            });
+        }
+
+        self.write_within(self.timeout(), || {
+            Error::sync_lock_for_too_long(&self.lock_data, "sync_write")
+        })
+    }
+
+    /// Locks with exclusive write access, returning
+    /// [Error::AcquireTimeout] if it cannot be acquired within `timeout`.
+    ///
+    /// Unless the lock is [forbidden in async](Self::forbid_async), it must be
+    /// acquired inside a `with_deadlock_check` future or a
+    /// [blocking::with_deadlock_check](crate::blocking::with_deadlock_check),
+    /// failing with [Error::NotDeadlockCheckFuture] otherwise.
+    #[track_caller]
+    pub fn write_for(&self, timeout: Duration) -> Result<RwLockWriteGuard<'_, T>> {
+        self.write_within(timeout, || {
+            Error::acquire_timeout(&self.lock_data, "sync_write", timeout)
+        })
+    }
+
+    /// Same as [write_for](Self::write_for), failing with `timed_out()` on expiry.
+    #[track_caller]
+    fn write_within<F>(&self, timeout: Duration, timed_out: F) -> Result<RwLockWriteGuard<'_, T>>
+    where
+        F: FnOnce() -> Error,
+    {
+        if self.forbid_async {
+            super::check_forbid_async(&self.lock_data, "sync_write")?;
+
+            let (_active, guard) = super::lock_forbidden(&self.lock_data, "sync_write", || {
+                self.rwlock.try_write_for(timeout).ok_or_else(timed_out)
+            })?;
+
+            return Ok(RwLockWriteGuard {
+                _active,
+                guard,
+                optimistic: self.optimistic.as_ref(),
+            });
+        }
+
+        if let Some(guard) = self.rwlock.try_write() {
+            return Ok(RwLockWriteGuard {
+                _active: Some(LockHeldGuard::new_no_wait(&self.lock_data, "sync_write")?),
+                guard,
+                optimistic: self.optimistic.as_ref(),
+            });
+        }
+
+        let wait = LockAwaitGuard::new(&self.lock_data, "sync_write")?;
+
+        match self.rwlock.try_write_for(timeout) {
+            Some(guard) => Ok(RwLockWriteGuard {
+                _active: Some(LockHeldGuard::new(wait)?),
+                guard,
+                optimistic: self.optimistic.as_ref(),
+            }),
+            None => Err(timed_out()),
+        }
+    }
+
+    /// Locks with shared read access if no write is held, returning `None`
+    /// without waiting otherwise.
+    #[track_caller]
+    pub fn try_read(&self) -> Result<Option<RwLockReadGuard<'_, T>>> {
+        if self.forbid_async {
+            super::check_forbid_async(&self.lock_data, "sync_read")?;
+        }
+
+        let Some(guard) = self.rwlock.try_read() else {
+            return Ok(None);
+        };
+
+        Ok(Some(RwLockReadGuard {
+            _active: self.try_active("sync_read")?,
+            guard,
+        }))
+    }
+
+    /// Locks with exclusive write access if the lock is free, returning `None`
+    /// without waiting otherwise.
+    #[track_caller]
+    pub fn try_write(&self) -> Result<Option<RwLockWriteGuard<'_, T>>> {
+        if self.forbid_async {
+            super::check_forbid_async(&self.lock_data, "sync_write")?;
+        }
+
+        let Some(guard) = self.rwlock.try_write() else {
+            return Ok(None);
+        };
+
+        Ok(Some(RwLockWriteGuard {
+            _active: self.try_active("sync_write")?,
+            guard,
+            optimistic: self.optimistic.as_ref(),
+        }))
+    }
+
+    /// Tracks a lock acquired without waiting.
+    fn try_active(&self, op: &'static str) -> Result<Option<LockHeldGuard<'_>>> {
+        match self.forbid_async {
+            true => super::held_forbidden(&self.lock_data, op),
+            false => LockHeldGuard::new_no_wait(&self.lock_data, op).map(Some),
+        }
+    }
+}
+
+pub struct RwLockReadGuard<'a, T> {
+    _active: Option<LockHeldGuard<'a>>,
+    guard: parking_lot::RwLockReadGuard<'a, T>,
+}
+
+impl<'a, T> RwLockReadGuard<'a, T> {
+    /// Returns the underlying `parking_lot` guard.
+    ///
+    /// The lock is no longer tracked from this point: the deadlock detection
+    /// and the telemetry consider the lock released while the returned guard
+    /// is still holding it.
+    pub fn into_parking_lot_guard(self) -> parking_lot::RwLockReadGuard<'a, T> {
+        self.guard
+    }
+
+    /// Makes a guard on a part of the locked value, holding the lock
+    /// tracked until dropped.
+    pub fn map<U: ?Sized, F>(s: Self, f: F) -> MappedRwLockReadGuard<'a, U>
+    where
+        F: FnOnce(&T) -> &U,
+    {
+        MappedRwLockReadGuard {
+            _active: s._active,
+            guard: parking_lot::RwLockReadGuard::map(s.guard, f),
+        }
+    }
+}
+
+impl<T> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+pub struct RwLockWriteGuard<'a, T> {
+    _active: Option<LockHeldGuard<'a>>,
+    guard: parking_lot::RwLockWriteGuard<'a, T>,
+    optimistic: Option<&'a Optimistic<T>>,
+}
+
+impl<'a, T> RwLockWriteGuard<'a, T> {
+    /// Returns the underlying `parking_lot` guard.
+    ///
+    /// The lock is no longer tracked from this point: the deadlock detection
+    /// and the telemetry consider the lock released while the returned guard
+    /// is still holding it. The optimistic copy, if any, is marked outdated
+    /// until the next tracked write.
+    pub fn into_parking_lot_guard(self) -> parking_lot::RwLockWriteGuard<'a, T> {
+        if let Some(optimistic) = self.optimistic {
+            optimistic.set_dirty();
+        }
+
+        let s = ManuallyDrop::new(self);
+
+        // SAFETY: each field is moved out once from a guard which is never dropped.
+        let (active, guard) = unsafe { (ptr::read(&s._active), ptr::read(&s.guard)) };
+
+        drop(active);
+        guard
+    }
+
+    /// Makes a guard on a part of the locked value, holding the lock
+    /// tracked until dropped.
+    ///
+    /// The mapped guard cannot publish the whole value: the copy of an
+    /// [optimistic](RwLock::optimistic) lock is outdated until the next write.
+    pub fn map<U: ?Sized, F>(s: Self, f: F) -> MappedRwLockWriteGuard<'a, U>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        if let Some(optimistic) = s.optimistic {
+            optimistic.set_dirty();
+        }
+
+        let s = ManuallyDrop::new(s);
+
+        // SAFETY: each field is moved out once from a guard which is never dropped.
+        let (active, guard) = unsafe { (ptr::read(&s._active), ptr::read(&s.guard)) };
+
+        MappedRwLockWriteGuard {
+            _active: active,
+            guard: parking_lot::RwLockWriteGuard::map(guard, f),
+        }
+    }
+}
+
+impl<T> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        if let Some(optimistic) = self.optimistic {
+            optimistic.publish(&self.guard);
+        }
+    }
+}
+
+impl<T> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for RwLockWriteGuard<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+pub struct MappedRwLockReadGuard<'a, T: ?Sized> {
+    _active: Option<LockHeldGuard<'a>>,
+    guard: parking_lot::MappedRwLockReadGuard<'a, T>,
+}
+
+impl<T: ?Sized> Deref for MappedRwLockReadGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+pub struct MappedRwLockWriteGuard<'a, T: ?Sized> {
+    _active: Option<LockHeldGuard<'a>>,
+    guard: parking_lot::MappedRwLockWriteGuard<'a, T>,
+}
+
+impl<T: ?Sized> Deref for MappedRwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized> DerefMut for MappedRwLockWriteGuard<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn write_for_times_out() -> Result<()> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = RwLock::new(0, "main_lock").with_timeout(Duration::ZERO);
+            let other = lock.rwlock.write();
+
+            assert_eq!(
+                lock.read_for(Duration::ZERO).err(),
+                Some(Error::AcquireTimeout)
+            );
+            assert_eq!(
+                lock.read().err(),
+                Some(Error::SyncLockForTooLong(Default::default()))
+            );
+
+            drop(other);
+            *lock.write_for(Duration::ZERO)? = 1;
+
+            assert_eq!(*lock.read()?, 1);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn waiters_while_contended() -> Result<()> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = RwLock::new(0, "main_lock");
+            let (tx, rx) = std::sync::mpsc::channel();
+
+            std::thread::scope(|s| {
+                s.spawn(|| {
+                    let _w = lock.rwlock.write();
+                    tx.send(()).unwrap();
+
+                    while lock.waiters() == 0 {
+                        std::thread::yield_now();
+                    }
+                });
+
+                rx.recv().unwrap();
+                *lock.write_for(Duration::from_secs(10))? = 1;
+
+                assert_eq!(lock.waiters(), 0);
+                Ok(())
+            })
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn forbid_async() -> Result<()> {
+    let lock = RwLock::new(0, "main_lock").forbid_async();
+
+    std::thread::scope(|s| s.spawn(|| *lock.write().unwrap() = 1).join().unwrap());
+
+    crate::with_deadlock_check(
+        async {
+            assert!(matches!(lock.read(), Err(Error::ForbiddenInAsync(_))));
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await?;
+
+    assert_eq!(lock.into_inner(), 1);
+    Ok(())
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn is_locked() -> Result<()> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = RwLock::new(0, "main_lock");
+
+            assert!(!lock.is_locked());
+
+            let r = lock.read()?;
+
+            assert!(lock.is_locked());
+            assert!(!lock.is_locked_exclusive());
+
+            drop(r);
+            let _w = lock.write()?;
+
+            assert!(lock.is_locked_exclusive());
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn mapped_guards() -> Result<()> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = RwLock::new([1, 2], "main_lock").optimistic();
+
+            *RwLockWriteGuard::map(lock.write()?, |v| &mut v[1]) = 3;
+            assert_eq!(lock.read_optimistic(), None);
+
+            let second = RwLockReadGuard::map(lock.read()?, |v| &v[1]);
+
+            assert_eq!(*second, 3);
+            assert_eq!(lock.lock_data.holders("sync_read"), 1);
+
+            drop(second);
+            drop(lock.write()?);
+
+            assert_eq!(lock.read_optimistic(), Some([1, 3]));
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn try_write_while_read() -> Result<()> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = RwLock::new(0, "main_lock");
+            let other = lock.rwlock.read();
+
+            assert!(lock.try_write()?.is_none());
+            assert_eq!(*lock.try_read()?.unwrap(), 0);
+
+            drop(other);
+            *lock.try_write()?.unwrap() = 1;
+
+            assert_eq!(lock.lock_data.holders("sync_write"), 0);
+            assert_eq!(*lock.read()?, 1);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
@@ -0,0 +1,282 @@
+use crate::{
+    primitives::{LockAwaitGuard, LockData, LockHeldGuard},
+    Error, Result, Thresholds,
+};
+
+/// A [tokio::sync::Semaphore] participating in the deadlock detection: each
+/// task holding permits holds the semaphore.
+///
+/// A task may take several permits. Once all are taken, awaiting one is
+/// reported as a deadlock only if no holder may release its permit: the task
+/// holds them all, or each other holder awaits a lock held by the task.
+pub struct Semaphore {
+    lock_data: LockData,
+    semaphore: tokio::sync::Semaphore,
+}
+
+impl Semaphore {
+    pub const fn new(permits: usize, name: &'static str) -> Self {
+        let mut lock_data = LockData::new(name);
+
+        lock_data.permits = true;
+
+        Self {
+            lock_data,
+            semaphore: tokio::sync::Semaphore::const_new(permits),
+        }
+    }
+
+    /// Reports the metrics of this semaphore under `group` instead of its name.
+    pub const fn with_group(mut self, group: &'static str) -> Self {
+        self.lock_data.group = group;
+        self
+    }
+
+    /// Overrides the durations after which waiting for or holding
+    /// a permit is reported in the telemetry.
+    pub const fn with_thresholds(mut self, thresholds: Thresholds) -> Self {
+        self.lock_data.thresholds = thresholds;
+        self
+    }
+
+    /// Acquires a permit, or fails with [Error::Closed] once the semaphore is closed.
+    pub async fn acquire(&self) -> Result<SemaphorePermit<'_>> {
+        if let Ok(permit) = self.semaphore.try_acquire() {
+            let active = LockHeldGuard::new_no_wait(&self.lock_data, "semaphore")?;
+            return Ok(SemaphorePermit::new(self, active, permit));
+        }
+
+        let wait = LockAwaitGuard::new(&self.lock_data, "semaphore")?;
+
+        #[cfg(feature = "telemetry")]
+        let since = crate::clock::now();
+
+        let permit = self.semaphore.acquire().await.map_err(|_| Error::Closed)?;
+
+        #[cfg(feature = "telemetry")]
+        metrics::histogram!("semaphore_wait_latency", "name" => self.lock_data.group)
+            .record(crate::clock::elapsed(since).as_secs_f64() * 1000.0);
+
+        let active = LockHeldGuard::new(wait)?;
+        Ok(SemaphorePermit::new(self, active, permit))
+    }
+
+    pub fn add_permits(&self, n: usize) {
+        self.semaphore.add_permits(n);
+
+        #[cfg(feature = "telemetry")]
+        self.record_permits();
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+
+    /// Closes the semaphore: the tasks awaiting a permit, and the next ones,
+    /// fail with [Error::Closed].
+    pub fn close(&self) {
+        self.semaphore.close()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.semaphore.is_closed()
+    }
+
+    /// The approximate number of tasks waiting for a permit.
+    #[cfg(not(feature = "unchecked"))]
+    pub fn waiters(&self) -> usize {
+        self.lock_data.waiters()
+    }
+
+    #[cfg(feature = "telemetry")]
+    fn record_permits(&self) {
+        metrics::gauge!("semaphore_permits_gauge", "name" => self.lock_data.group)
+            .set(self.semaphore.available_permits() as f64);
+    }
+}
+
+pub struct SemaphorePermit<'a> {
+    _active: LockHeldGuard<'a>,
+
+    /// Taken on drop to report the permits available once released.
+    permit: Option<tokio::sync::SemaphorePermit<'a>>,
+
+    #[cfg_attr(not(feature = "telemetry"), allow(dead_code))]
+    semaphore: &'a Semaphore,
+}
+
+impl<'a> SemaphorePermit<'a> {
+    fn new(
+        semaphore: &'a Semaphore,
+        active: LockHeldGuard<'a>,
+        permit: tokio::sync::SemaphorePermit<'a>,
+    ) -> Self {
+        #[cfg(feature = "telemetry")]
+        semaphore.record_permits();
+
+        Self {
+            _active: active,
+            permit: Some(permit),
+            semaphore,
+        }
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        drop(self.permit.take());
+
+        #[cfg(feature = "telemetry")]
+        self.semaphore.record_permits();
+    }
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn acquire_until_closed() -> Result<()> {
+    let semaphore = Semaphore::new(2, "main_semaphore");
+
+    let holder = crate::with_deadlock_check(
+        async {
+            let _first = semaphore.acquire().await?;
+            let _second = semaphore.acquire().await?;
+
+            assert_eq!(
+                semaphore.acquire().await.err(),
+                Some(Error::DeadlockDetected(Default::default()))
+            );
+
+            while semaphore.waiters() == 0 {
+                tokio::task::yield_now().await;
+            }
+
+            semaphore.close();
+            Ok(())
+        },
+        "holder".into(),
+    );
+
+    let waiter = crate::with_deadlock_check(
+        async {
+            while semaphore.available_permits() > 0 {
+                tokio::task::yield_now().await;
+            }
+
+            assert_eq!(semaphore.acquire().await.err(), Some(Error::Closed));
+            Ok(())
+        },
+        "waiter".into(),
+    );
+
+    let (a, b) = tokio::join!(holder, waiter);
+
+    assert_eq!(semaphore.available_permits(), 2);
+    a.and(b)
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn deadlock_once_no_holder_may_release() -> Result<()> {
+    use super::async_mutex::Mutex;
+    use crate::with_deadlock_check;
+    use tokio::task::yield_now;
+
+    let semaphore = Semaphore::new(2, "main_semaphore");
+    let lock = Mutex::new((), "main_lock");
+
+    // takes a permit, then waits for the lock.
+    let blocked = |name: &'static str| {
+        let (semaphore, lock) = (&semaphore, &lock);
+
+        with_deadlock_check(
+            async move {
+                while !lock.is_locked() {
+                    yield_now().await;
+                }
+
+                let _permit = semaphore.acquire().await?;
+                lock.lock().await.map(drop)
+            },
+            name.into(),
+        )
+    };
+
+    let waiter = with_deadlock_check(
+        async {
+            let guard = lock.lock().await?;
+
+            while lock.lock_data.waiters() < 2 {
+                yield_now().await;
+            }
+
+            let r = semaphore.acquire().await.map(drop);
+
+            drop(guard);
+            r
+        },
+        "waiter".into(),
+    );
+
+    let (a, b, w) = tokio::join!(blocked("a"), blocked("b"), waiter);
+
+    assert_eq!(w, Err(Error::DeadlockDetected(Default::default())));
+    a.and(b)
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn wait_for_a_holder_which_may_release() -> Result<()> {
+    use super::async_mutex::Mutex;
+    use crate::with_deadlock_check;
+    use tokio::task::yield_now;
+
+    let semaphore = Semaphore::new(2, "main_semaphore");
+    let lock = Mutex::new((), "main_lock");
+
+    let blocked = with_deadlock_check(
+        async {
+            while !lock.is_locked() {
+                yield_now().await;
+            }
+
+            let _permit = semaphore.acquire().await?;
+            lock.lock().await.map(drop)
+        },
+        "blocked".into(),
+    );
+
+    let releasing = with_deadlock_check(
+        async {
+            let permit = semaphore.acquire().await?;
+
+            while semaphore.waiters() == 0 {
+                yield_now().await;
+            }
+
+            drop(permit);
+            Ok(())
+        },
+        "releasing".into(),
+    );
+
+    let waiter = with_deadlock_check(
+        async {
+            let guard = lock.lock().await?;
+
+            while lock.lock_data.waiters() == 0 || semaphore.available_permits() > 0 {
+                yield_now().await;
+            }
+
+            let permit = semaphore.acquire().await?;
+
+            drop(guard);
+            drop(permit);
+            Ok(())
+        },
+        "waiter".into(),
+    );
+
+    let (a, b, w) = tokio::join!(blocked, releasing, waiter);
+
+    a.and(b).and(w)
+}
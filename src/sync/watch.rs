@@ -0,0 +1,99 @@
+//! A [tokio::sync::watch] channel warning in the telemetry when its changes
+//! are awaited while the task holds a lock.
+
+use crate::{Error, Result};
+use tokio::sync::watch::{self, Ref};
+
+/// Creates a channel named `name` in the telemetry, holding `init` until the
+/// first send.
+pub fn channel<T>(init: T, name: &'static str) -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = watch::channel(init);
+    (Sender { name, sender }, Receiver { name, receiver })
+}
+
+pub struct Sender<T> {
+    name: &'static str,
+    sender: watch::Sender<T>,
+}
+
+impl<T> Sender<T> {
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.sender.borrow()
+    }
+
+    pub fn receiver_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+
+    /// Sends `value` to the receivers, failing with [Error::Closed] if there is none.
+    pub fn send(&self, value: T) -> Result<()> {
+        self.sender.send(value).map_err(|_| Error::Closed)
+    }
+
+    /// Changes the value in place and notifies the receivers, even without any.
+    pub fn send_modify<F: FnOnce(&mut T)>(&self, f: F) {
+        self.sender.send_modify(f)
+    }
+
+    pub fn subscribe(&self) -> Receiver<T> {
+        Receiver {
+            name: self.name,
+            receiver: self.sender.subscribe(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Receiver<T> {
+    #[cfg_attr(not(feature = "telemetry"), allow(dead_code))]
+    name: &'static str,
+    receiver: watch::Receiver<T>,
+}
+
+impl<T> Receiver<T> {
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.receiver.borrow()
+    }
+
+    /// Same as [borrow](Self::borrow), marking the value as seen.
+    pub fn borrow_and_update(&mut self) -> Ref<'_, T> {
+        self.receiver.borrow_and_update()
+    }
+
+    /// Waits for a value not seen yet, failing with [Error::Closed] once the
+    /// sender is dropped.
+    ///
+    /// With the `telemetry` feature, a "Lock held" warning is logged if the
+    /// task holds a lock meanwhile.
+    pub async fn changed(&mut self) -> Result<()> {
+        #[cfg(feature = "telemetry")]
+        crate::warn_lock_held();
+
+        #[cfg(feature = "telemetry")]
+        let since = crate::clock::now();
+
+        let r = self.receiver.changed().await.map_err(|_| Error::Closed);
+
+        #[cfg(feature = "telemetry")]
+        metrics::histogram!("watch_wait_latency", "name" => self.name)
+            .record(crate::clock::elapsed(since).as_secs_f64() * 1000.0);
+
+        r
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn changed_until_closed() -> Result<()> {
+    let (sender, mut receiver) = channel(0, "main_watch");
+
+    sender.send(1)?;
+    receiver.changed().await?;
+
+    assert_eq!(*receiver.borrow_and_update(), 1);
+
+    drop(sender);
+    assert_eq!(receiver.changed().await, Err(Error::Closed));
+
+    Ok(())
+}
@@ -0,0 +1,43 @@
+//! Helpers to assert the state of the locks in tests.
+
+use crate::{dependency_graph, primitives::locks_held, with_deadlock_check};
+use std::{fmt::Debug, future::Future};
+
+/// Panics if the current task holds any lock.
+#[track_caller]
+pub fn assert_no_locks_held() {
+    let names = held_locks();
+
+    assert!(names.is_empty(), "locks held: {names:?}");
+}
+
+/// Panics with the offending cycle if the locks observed so far in the
+/// process have been acquired in inconsistent orders.
+///
+/// See [lock_dependency_graph](crate::lock_dependency_graph).
+#[track_caller]
+pub fn assert_lock_order_acyclic() {
+    if let Some(cycle) = dependency_graph::find_cycle() {
+        panic!("lock order cycle: {}", cycle.join(" -> "));
+    }
+}
+
+/// Returns the names of the locks held by the current task.
+///
+/// Returns an empty list when not running inside a `with_deadlock_check` future.
+pub fn held_locks() -> Vec<&'static str> {
+    locks_held::names()
+}
+
+/// Runs the future inside a `with_deadlock_check` named `task_name`,
+/// panicking if it returns an error.
+pub async fn run_with_deadlock_check<F, R, E>(f: F, task_name: &str) -> R
+where
+    F: Future<Output = Result<R, E>>,
+    E: Debug,
+{
+    match with_deadlock_check(f, task_name.to_string()).await {
+        Ok(v) => v,
+        Err(e) => panic!("{task_name} failed: {e:?}"),
+    }
+}
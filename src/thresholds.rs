@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+#[cfg(feature = "telemetry")]
+const LONG_INIT: Duration = Duration::from_secs(1);
+#[cfg(feature = "telemetry")]
+const LONG_LOCK: Duration = Duration::from_secs(30);
+#[cfg(all(feature = "telemetry", not(feature = "unchecked")))]
+const LONG_WAIT: Duration = Duration::from_millis(500);
+
+/// Durations after which a warning is logged in the telemetry.
+///
+/// Unset values fall back to the thresholds of the [Config](crate::Config),
+/// then to the defaults: 500 ms for waiting on a lock, 30 seconds for holding it
+/// and 1 second for initializing a lazily initialized value.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Thresholds {
+    /// Maximum time waiting to acquire the lock.
+    pub wait: Option<Duration>,
+
+    /// Maximum time holding a read.
+    pub read: Option<Duration>,
+
+    /// Maximum time holding the queue.
+    pub queue: Option<Duration>,
+
+    /// Maximum time holding an exclusive access (write or lock).
+    pub write: Option<Duration>,
+
+    /// Maximum time initializing a lazily initialized value.
+    pub init: Option<Duration>,
+}
+
+impl Thresholds {
+    pub const fn new() -> Self {
+        Self {
+            wait: None,
+            read: None,
+            queue: None,
+            write: None,
+            init: None,
+        }
+    }
+
+    #[cfg(feature = "telemetry")]
+    pub(crate) fn held(&self, op: &str) -> Duration {
+        let config = &crate::Config::get().thresholds;
+
+        self.held_for(op)
+            .or_else(|| config.held_for(op))
+            .unwrap_or(LONG_LOCK)
+    }
+
+    #[cfg(feature = "telemetry")]
+    pub(crate) fn init(&self) -> Duration {
+        self.init
+            .or(crate::Config::get().thresholds.init)
+            .unwrap_or(LONG_INIT)
+    }
+
+    #[cfg(feature = "telemetry")]
+    fn held_for(&self, op: &str) -> Option<Duration> {
+        match op {
+            "read" | "sync_read" => self.read,
+            "queue" => self.queue,
+            _ => self.write,
+        }
+    }
+
+    #[cfg(all(feature = "telemetry", not(feature = "unchecked")))]
+    pub(crate) fn wait(&self) -> Duration {
+        self.wait
+            .or(crate::Config::get().thresholds.wait)
+            .unwrap_or(LONG_WAIT)
+    }
+}
@@ -0,0 +1,183 @@
+use crate::{
+    sync::async_mutex::{Mutex, MutexGuard},
+    Error, QueueRwLock, QueueRwLockQueueGuard, QueueRwLockReadGuard,
+};
+use std::time::Duration;
+use tokio::time::{sleep_until, Instant};
+
+/// Limits the number of acquisitions of a lock per second.
+///
+/// Callers exceeding the limit wait for the next available slot before
+/// trying to acquire the lock.
+pub struct Throttled<L> {
+    interval: Duration,
+    lock: L,
+    slots: parking_lot::Mutex<Slots>,
+}
+
+#[derive(Default)]
+struct Slots {
+    /// The slot following the last one reserved.
+    next: Option<Instant>,
+
+    /// The slots given back by the cancelled callers, before `next`.
+    released: Vec<Instant>,
+}
+
+/// A slot reserved by a caller, given back if it is dropped before its time.
+struct Reservation<'a> {
+    done: bool,
+    interval: Duration,
+    slot: Instant,
+    slots: &'a parking_lot::Mutex<Slots>,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+
+        let mut slots = self.slots.lock();
+
+        if slots.next == Some(self.slot + self.interval) {
+            slots.next = Some(self.slot);
+        } else {
+            slots.released.push(self.slot);
+        }
+    }
+}
+
+impl<L> Throttled<L> {
+    /// Allows at most `per_second` acquisitions of `lock` per second.
+    pub fn new(lock: L, per_second: u32) -> Self {
+        assert!(per_second > 0, "per_second must be greater than zero");
+
+        Self {
+            interval: Duration::from_secs(1) / per_second,
+            lock,
+            slots: Default::default(),
+        }
+    }
+
+    /// Gives access to the lock without throttling.
+    pub fn inner(&self) -> &L {
+        &self.lock
+    }
+
+    pub fn into_inner(self) -> L {
+        self.lock
+    }
+
+    #[cfg_attr(not(feature = "telemetry"), allow(unused_variables))]
+    async fn throttle(&self, name: &'static str) {
+        let now = Instant::now();
+
+        let slot = {
+            let mut slots = self.slots.lock();
+
+            slots.released.retain(|slot| *slot >= now);
+
+            match slots
+                .released
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, slot)| **slot)
+            {
+                Some((index, _)) => slots.released.swap_remove(index),
+                None => {
+                    let slot = slots.next.map_or(now, |next| next.max(now));
+
+                    slots.next = Some(slot + self.interval);
+                    slot
+                }
+            }
+        };
+
+        let mut reservation = Reservation {
+            done: false,
+            interval: self.interval,
+            slot,
+            slots: &self.slots,
+        };
+
+        if slot > now {
+            #[cfg(feature = "telemetry")]
+            {
+                metrics::counter!("lock_throttled_counter", "name" => name).increment(1);
+                metrics::counter!("lock_throttled_ms", "name" => name)
+                    .increment((slot - now).as_millis() as u64);
+            }
+
+            sleep_until(slot).await;
+        }
+
+        reservation.done = true;
+    }
+}
+
+impl<T> Throttled<QueueRwLock<T>> {
+    /// Waits for a slot, then enqueue to gain access to the write.
+    pub async fn queue(&self) -> Result<QueueRwLockQueueGuard<'_, T>, Error> {
+        self.throttle(self.lock.lock_data.group).await;
+        self.lock.queue().await
+    }
+
+    /// Waits for a slot, then locks with shared read access.
+    pub async fn read(&self) -> Result<QueueRwLockReadGuard<'_, T>, Error> {
+        self.throttle(self.lock.lock_data.group).await;
+        self.lock.read().await
+    }
+}
+
+impl<T> Throttled<Mutex<T>> {
+    /// Waits for a slot, then locks the mutex.
+    pub async fn lock(&self) -> Result<MutexGuard<'_, T>, Error> {
+        self.throttle(self.lock.lock_data.group).await;
+        self.lock.lock().await
+    }
+}
+
+#[cfg(test)]
+#[tokio::test(start_paused = true)]
+async fn throttle_excess_callers() -> Result<(), Error> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = Throttled::new(QueueRwLock::new((), "main_lock"), 2);
+            let start = Instant::now();
+
+            for _ in 0..5 {
+                drop(lock.read().await?);
+            }
+
+            assert!(start.elapsed() >= Duration::from_secs(2));
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(test)]
+#[tokio::test(start_paused = true)]
+async fn cancelled_caller_gives_its_slot_back() -> Result<(), Error> {
+    crate::with_deadlock_check(
+        async move {
+            let lock = Throttled::new(QueueRwLock::new((), "main_lock"), 1);
+            let start = Instant::now();
+
+            drop(lock.read().await?);
+
+            let cancelled = tokio::time::timeout(Duration::from_millis(500), lock.read()).await;
+            assert!(cancelled.is_err());
+
+            drop(lock.read().await?);
+            assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
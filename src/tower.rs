@@ -0,0 +1,143 @@
+use crate::{deadlock::with_task, primitives::Task};
+use http::{HeaderName, Request};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// A tower [Layer] running each request inside a `with_deadlock_check`
+/// named `task_name`, followed by the request id in parentheses when the
+/// request has one, such as `api (42)`.
+///
+/// The request id is read from the `x-request-id` header as set by the
+/// `SetRequestIdLayer` of tower-http, so the lock errors naming the task can
+/// be correlated with the request. With the `telemetry` feature, the request
+/// is also instrumented with a span holding its method, path and request id.
+#[derive(Clone)]
+pub struct DeadlockCheckLayer {
+    request_id_header: HeaderName,
+
+    task_name: &'static str,
+}
+
+impl DeadlockCheckLayer {
+    pub fn new(task_name: &'static str) -> Self {
+        Self {
+            request_id_header: HeaderName::from_static("x-request-id"),
+            task_name,
+        }
+    }
+
+    /// Reads the request id from `header` instead of `x-request-id`.
+    pub fn with_request_id_header(mut self, header: HeaderName) -> Self {
+        self.request_id_header = header;
+        self
+    }
+}
+
+impl<S> Layer<S> for DeadlockCheckLayer {
+    type Service = DeadlockCheck<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        DeadlockCheck {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// The service of a [DeadlockCheckLayer].
+#[derive(Clone)]
+pub struct DeadlockCheck<S> {
+    inner: S,
+    layer: DeadlockCheckLayer,
+}
+
+impl<S, B> Service<Request<B>> for DeadlockCheck<S>
+where
+    S: Service<Request<B>>,
+    S::Error: 'static,
+    S::Future: Send + 'static,
+    S::Response: 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<S::Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let request_id = req
+            .headers()
+            .get(&self.layer.request_id_header)
+            .and_then(|v| v.to_str().ok());
+
+        #[cfg(feature = "telemetry")]
+        let span = tracing::info_span!(
+            "deadlock_check",
+            method = %req.method(),
+            path = req.uri().path(),
+            request_id,
+        );
+
+        let task_name = match request_id {
+            Some(id) => format!("{} ({id})", self.layer.task_name),
+            None => self.layer.task_name.to_string(),
+        };
+
+        let task = Arc::new(Task::new(task_name));
+        let f = with_task(self.inner.call(req), task);
+
+        #[cfg(feature = "telemetry")]
+        let f = tracing::Instrument::instrument(f, span);
+
+        Box::pin(f)
+    }
+}
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn runs_inside_deadlock_check() {
+    use std::{convert::Infallible, future::poll_fn};
+
+    struct Echo;
+
+    impl Service<Request<()>> for Echo {
+        type Response = String;
+        type Error = Infallible;
+        type Future = Pin<Box<dyn Future<Output = Result<String, Infallible>> + Send>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _: Request<()>) -> Self::Future {
+            Box::pin(async {
+                Ok(crate::primitives::task::current()
+                    .unwrap()
+                    .name()
+                    .to_string())
+            })
+        }
+    }
+
+    let mut service = DeadlockCheckLayer::new("api").layer(Echo);
+    let req = Request::builder()
+        .header("x-request-id", "42")
+        .body(())
+        .unwrap();
+
+    poll_fn(|cx| service.poll_ready(cx)).await.unwrap();
+    assert_eq!(service.call(req).await.unwrap(), "api (42)");
+
+    let req = Request::builder().body(()).unwrap();
+    assert_eq!(service.call(req).await.unwrap(), "api");
+}
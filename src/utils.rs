@@ -1,4 +1,13 @@
-use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+use std::{
+    future::Future,
+    pin::pin,
+    sync::{
+        atomic::{AtomicU64, Ordering::Relaxed},
+        Arc,
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+};
 
 static ID: AtomicU64 = AtomicU64::new(1);
 
@@ -9,3 +18,60 @@ pub(crate) fn new_id() -> u64 {
 
     id
 }
+
+/// Polls `f` to completion on the current thread, parking it while pending.
+///
+/// `f` must not depend on a runtime, as the futures of `tokio::sync`.
+pub(crate) fn block_on<F: Future>(f: F) -> F::Output {
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut f = pin!(f);
+
+    loop {
+        if let Poll::Ready(out) = f.as_mut().poll(&mut cx) {
+            return out;
+        }
+
+        thread::park();
+    }
+}
+
+/// Runs the initializer of a lazily initialized value, reporting its
+/// duration in the telemetry.
+#[cfg_attr(not(feature = "telemetry"), allow(unused_variables))]
+pub(crate) async fn timed_init<F>(name: &'static str, f: F) -> F::Output
+where
+    F: Future,
+{
+    #[cfg(feature = "telemetry")]
+    let instant = crate::clock::now();
+
+    let out = f.await;
+
+    #[cfg(feature = "telemetry")]
+    {
+        let elapsed = crate::clock::elapsed(instant);
+
+        if elapsed > crate::Config::get().thresholds.init() {
+            tracing::warn!(
+                elapsed_ms = elapsed.as_millis(),
+                name = name,
+                "Initialization for too long",
+            );
+        }
+
+        metrics::counter!("once_init_counter", "name" => name).increment(1);
+        metrics::histogram!("once_init_latency", "name" => name)
+            .record(elapsed.as_secs_f64() * 1000.0);
+    }
+
+    out
+}
@@ -0,0 +1,281 @@
+use crate::{
+    primitives::{LockAwaitGuard, LockData, LockHeldGuard},
+    Result,
+};
+use std::{
+    ops::{Deref, DerefMut},
+    time::Duration,
+};
+
+/// A lock living outside of this process (a database advisory lock, a file
+/// lock...) registered with the deadlock detection.
+///
+/// The actual locking is left to the caller, which reports when it starts
+/// waiting for the lock with [wait](Self::wait) and when it holds the lock
+/// with [held](Self::held) or [VirtualLockWait::held].
+pub struct VirtualLock {
+    pub(crate) lock_data: LockData,
+}
+
+impl VirtualLock {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            lock_data: LockData::new(name),
+        }
+    }
+
+    /// Reports that the current task starts waiting for the lock.
+    pub fn wait(&self) -> Result<VirtualLockWait<'_>> {
+        self.wait_as("virtual")
+    }
+
+    /// Same as [wait](Self::wait), reporting the operation as `op` (`"checkout"`,
+    /// `"flush"`...) in the metrics instead of `"virtual"`.
+    ///
+    /// The deadlock detection treats `"read"` as shared with the other reads
+    /// and any other op as exclusive.
+    pub fn wait_as(&self, op: &'static str) -> Result<VirtualLockWait<'_>> {
+        Ok(VirtualLockWait(LockAwaitGuard::new(&self.lock_data, op)?))
+    }
+
+    /// Reports that the current task holds the lock without waiting for it.
+    pub fn held(&self) -> Result<VirtualLockHeld<'_>> {
+        self.held_as("virtual")
+    }
+
+    /// Same as [held](Self::held), reporting the operation as `op`.
+    pub fn held_as(&self, op: &'static str) -> Result<VirtualLockHeld<'_>> {
+        let wait = self.wait_as(op)?;
+        wait.held()
+    }
+
+    /// Wraps an open database transaction (`sqlx::Transaction`, a diesel
+    /// connection inside a transaction...), holding this lock until the
+    /// guard is dropped or [into_inner](TransactionGuard::into_inner) is called.
+    ///
+    /// A warning is logged each time the task awaits a lock while the
+    /// transaction is open.
+    pub fn transaction<Tx>(&self, tx: Tx) -> Result<TransactionGuard<'_, Tx>> {
+        let wait = LockAwaitGuard::new(&self.lock_data, "transaction")?;
+
+        Ok(TransactionGuard {
+            _held: LockHeldGuard::new(wait)?,
+            tx,
+        })
+    }
+}
+
+/// Keeps the current task waiting for the [VirtualLock] until dropped.
+pub struct VirtualLockWait<'a>(LockAwaitGuard<'a>);
+
+impl<'a> VirtualLockWait<'a> {
+    /// Reports that the lock has been obtained.
+    pub fn held(self) -> Result<VirtualLockHeld<'a>> {
+        Ok(VirtualLockHeld(LockHeldGuard::new(self.0)?))
+    }
+}
+
+/// Keeps the [VirtualLock] held by the current task until dropped.
+pub struct VirtualLockHeld<'a>(LockHeldGuard<'a>);
+
+impl VirtualLockHeld<'_> {
+    pub fn elapsed(&self) -> Duration {
+        self.0.elapsed()
+    }
+}
+
+/// An open transaction registered as holding a [VirtualLock].
+pub struct TransactionGuard<'a, Tx> {
+    _held: LockHeldGuard<'a>,
+    tx: Tx,
+}
+
+impl<Tx> TransactionGuard<'_, Tx> {
+    /// Releases the lock and returns the transaction, to commit or rollback it.
+    pub fn into_inner(self) -> Tx {
+        self.tx
+    }
+}
+
+impl<Tx> Deref for TransactionGuard<'_, Tx> {
+    type Target = Tx;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.tx
+    }
+}
+
+impl<Tx> DerefMut for TransactionGuard<'_, Tx> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.tx
+    }
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn virtual_lock_cycle() {
+    use crate::{with_deadlock_check, Error, QueueRwLock};
+    use tokio::{sync::oneshot, task::yield_now};
+
+    let db = VirtualLock::new("db:orders_row");
+    let lock = QueueRwLock::new((), "main_lock");
+    let (tx, rx) = oneshot::channel();
+
+    let a = with_deadlock_check(
+        async {
+            let _db = db.held()?;
+
+            rx.await.unwrap();
+            lock.queue().await.map(drop)
+        },
+        "a".into(),
+    );
+
+    let b = with_deadlock_check(
+        async {
+            let q = lock.queue().await?;
+
+            tx.send(()).unwrap();
+
+            // let `a` wait on the lock.
+            for _ in 0..3 {
+                yield_now().await;
+            }
+
+            let r = db.wait().map(drop);
+            drop(q);
+            r
+        },
+        "b".into(),
+    );
+
+    let (a, b) = tokio::join!(a, b);
+
+    assert!(a.is_ok());
+    assert_eq!(b.unwrap_err(), Error::DeadlockDetected(Default::default()));
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn custom_ops() -> Result<()> {
+    crate::with_deadlock_check(
+        async move {
+            let pool = VirtualLock::new("db:pool");
+            let _checkout = pool.held_as("checkout")?;
+
+            assert_eq!(pool.lock_data.holders("checkout"), 1);
+            assert_eq!(pool.lock_data.holders("virtual"), 0);
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn transaction_cycle() {
+    use crate::{with_deadlock_check, Error, QueueRwLock};
+    use tokio::{sync::oneshot, task::yield_now};
+
+    #[cfg(feature = "telemetry")]
+    let warnings = {
+        use parking_lot::Mutex;
+        use std::{fmt, sync::Arc};
+        use tracing::{field::Field, span, Event, Id, Metadata, Subscriber};
+
+        /// Records the message of each event.
+        #[derive(Clone, Default)]
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+
+        impl tracing::field::Visit for Recorder {
+            fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                if field.name() == "message" {
+                    self.0.lock().push(format!("{value:?}"));
+                }
+            }
+        }
+
+        impl Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, _: &span::Attributes<'_>) -> Id {
+                Id::from_u64(1)
+            }
+
+            fn record(&self, _: &Id, _: &span::Record<'_>) {}
+
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                event.record(&mut self.clone());
+            }
+
+            fn enter(&self, _: &Id) {}
+
+            fn exit(&self, _: &Id) {}
+        }
+
+        let recorder = Recorder::default();
+        (
+            Arc::clone(&recorder.0),
+            tracing::subscriber::set_default(recorder),
+        )
+    };
+
+    let db = VirtualLock::new("db:orders");
+    let lock = QueueRwLock::new((), "main_lock");
+    let (tx, rx) = oneshot::channel();
+
+    let a = with_deadlock_check(
+        async {
+            let tx = db.transaction("orders_tx")?;
+
+            rx.await.unwrap();
+            lock.queue().await.map(drop)?;
+
+            assert_eq!(tx.into_inner(), "orders_tx");
+            Ok(())
+        },
+        "a".into(),
+    );
+
+    let b = with_deadlock_check(
+        async {
+            let q = lock.queue().await?;
+
+            tx.send(()).unwrap();
+
+            // let `a` wait on the lock.
+            for _ in 0..3 {
+                yield_now().await;
+            }
+
+            let r = db.transaction(()).map(drop);
+            drop(q);
+            r
+        },
+        "b".into(),
+    );
+
+    let (a, b): (Result<()>, _) = tokio::join!(a, b);
+
+    assert!(a.is_ok());
+    assert_eq!(b.unwrap_err(), Error::DeadlockDetected(Default::default()));
+
+    #[cfg(feature = "telemetry")]
+    assert_eq!(
+        warnings
+            .0
+            .lock()
+            .iter()
+            .filter(|m| *m == "Lock awaited while a transaction is open")
+            .count(),
+        1
+    );
+}
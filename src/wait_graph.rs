@@ -0,0 +1,78 @@
+use crate::primitives::task;
+use std::fmt::Write;
+
+/// Returns the graph of the live `with_deadlock_check` tasks in the DOT format
+/// of Graphviz.
+///
+/// An edge goes from each lock to the tasks holding it, and from each task
+/// to the lock it awaits, dashed. A cycle is a deadlock.
+pub fn generate_wait_graph_dot() -> String {
+    let mut dot = String::from("digraph locks {\n");
+    let mut locks = Vec::new();
+
+    for (i, task) in task::live().iter().enumerate() {
+        let _ = writeln!(dot, "    t{i} [label={:?}, shape=box];", &*task.name());
+
+        for (id, name, op) in task.held() {
+            locks.push((id, name));
+            let _ = writeln!(dot, "    l{id} -> t{i} [label={op:?}];");
+        }
+
+        if let Some((id, name, op)) = task.awaiting() {
+            locks.push((id, name));
+            let _ = writeln!(dot, "    t{i} -> l{id} [label={op:?}, style=dashed];");
+        }
+    }
+
+    locks.sort_unstable();
+    locks.dedup();
+
+    for (id, name) in locks {
+        let _ = writeln!(dot, "    l{id} [label={name:?}];");
+    }
+
+    dot.push('}');
+    dot
+}
+
+#[cfg(all(test, not(feature = "unchecked")))]
+#[tokio::test]
+async fn held_and_awaited_locks() -> crate::Result<()> {
+    use crate::{with_deadlock_check, QueueRwLock};
+    use tokio::sync::oneshot;
+
+    let lock = QueueRwLock::new((), "graph_lock");
+    let (tx, rx) = oneshot::channel();
+
+    let holder = with_deadlock_check(
+        async {
+            let _q = lock.queue().await?;
+
+            tx.send(()).unwrap();
+            tokio::task::yield_now().await;
+
+            let dot = generate_wait_graph_dot();
+
+            assert!(dot.contains("[label=\"graph_holder\", shape=box]"));
+            assert!(dot.contains("[label=\"graph_waiter\", shape=box]"));
+            assert!(dot.contains("[label=\"queue\"]"));
+            assert!(dot.contains("[label=\"queue\", style=dashed]"));
+            assert!(dot.contains("[label=\"graph_lock\"]"));
+
+            Ok(())
+        },
+        "graph_holder".into(),
+    );
+
+    let waiter = with_deadlock_check(
+        async {
+            rx.await.unwrap();
+            lock.queue().await.map(drop)
+        },
+        "graph_waiter".into(),
+    );
+
+    let (a, b) = tokio::join!(holder, waiter);
+
+    a.and(b)
+}
@@ -0,0 +1,27 @@
+//! Runs in its own binary since the clock set is process-wide and can't be unset.
+#![cfg(not(feature = "unchecked"))]
+
+use async_cell_lock::{set_clock, with_deadlock_check, MockClock, QueueRwLock, Result};
+use std::{sync::Arc, time::Duration};
+
+#[tokio::test]
+async fn mock_clock_elapsed() -> Result<()> {
+    let clock = Arc::new(MockClock::new());
+
+    set_clock(Arc::clone(&clock));
+
+    with_deadlock_check(
+        async move {
+            let lock = QueueRwLock::new((), "main_lock");
+            let q = lock.queue().await?;
+
+            clock.advance(Duration::from_secs(31));
+
+            assert!(q.elapsed() >= Duration::from_secs(31));
+
+            Ok(())
+        },
+        "lock_test".into(),
+    )
+    .await
+}